@@ -1,7 +1,121 @@
+/// A line of user input, as understood by `parser::parse_command`
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Command {
-    Help,
+    /// `/help [command]`
+    Help { command: Option<String> },
     Clear,
     Quit,
+    /// `/save [name]`
+    Save { name: Option<String> },
+    /// `/load <name>`
+    Load { name: String },
+    Sessions,
+    Compact,
+    Config,
+    /// `/model [name]`, showing the current model when no name is given
+    Model { name: Option<String> },
+    /// `/editor`, composing the next message in `$EDITOR`
+    Editor,
+    /// `/shell reset`
+    ShellReset,
+    /// `!command`, run directly in the shell
     ShellCommand(String),
+    /// Anything else is sent to the model
     ChatMessage(String),
 }
+
+/// How many arguments a slash command takes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Arity {
+    None,
+    Optional,
+    Required,
+}
+
+/// Name, arguments and help text of a slash command
+#[derive(Debug)]
+pub struct CommandSpec {
+    /// Name without the leading slash
+    pub name: &'static str,
+    pub arity: Arity,
+    pub usage: &'static str,
+    pub description: &'static str,
+}
+
+/// All slash commands, in the order they are listed by `/help`
+pub const COMMANDS: &[CommandSpec] = &[
+    CommandSpec {
+        name: "clear",
+        arity: Arity::None,
+        usage: "/clear",
+        description: "Clear the conversation history",
+    },
+    CommandSpec {
+        name: "help",
+        arity: Arity::Optional,
+        usage: "/help [command]",
+        description: "Show this help dialogue, or the usage of a single command",
+    },
+    CommandSpec {
+        name: "quit",
+        arity: Arity::None,
+        usage: "/quit",
+        description: "Quit the application",
+    },
+    CommandSpec {
+        name: "save",
+        arity: Arity::Optional,
+        usage: "/save [name]",
+        description: "Save the conversation (defaults to the current session name)",
+    },
+    CommandSpec {
+        name: "load",
+        arity: Arity::Required,
+        usage: "/load <name>",
+        description: "Load a saved conversation",
+    },
+    CommandSpec {
+        name: "sessions",
+        arity: Arity::None,
+        usage: "/sessions",
+        description: "List saved conversations",
+    },
+    CommandSpec {
+        name: "compact",
+        arity: Arity::None,
+        usage: "/compact",
+        description: "Summarize older messages to free up context",
+    },
+    CommandSpec {
+        name: "config",
+        arity: Arity::None,
+        usage: "/config",
+        description: "Show the effective configuration",
+    },
+    CommandSpec {
+        name: "model",
+        arity: Arity::Optional,
+        usage: "/model [name]",
+        description: "Show the current model, or switch to another model of the same provider",
+    },
+    CommandSpec {
+        name: "editor",
+        arity: Arity::None,
+        usage: "/editor",
+        description: "Compose the next message in $EDITOR, e.g. to paste long code or logs",
+    },
+    CommandSpec {
+        name: "shell",
+        arity: Arity::Required,
+        usage: "/shell reset",
+        description: "Restart the shell that commands run in, resetting its directory and environment",
+    },
+];
+
+impl CommandSpec {
+    /// Look up a command by name, with or without the leading slash
+    pub fn find(name: &str) -> Option<&'static CommandSpec> {
+        let name = name.strip_prefix('/').unwrap_or(name);
+        COMMANDS.iter().find(|spec| spec.name == name)
+    }
+}
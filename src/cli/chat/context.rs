@@ -1,16 +1,16 @@
 use std::env;
-use std::path::PathBuf;
+
+use super::tools::shell_session::ShellSession;
 
 pub struct ContextManager {
-    pub current_dir: PathBuf,
+    /// The shell commands run in, whose working directory is reported
+    pub shell: ShellSession,
     pub os_type: String,
     pub username: String,
 }
 
 impl ContextManager {
-    pub fn new() -> Self {
-        let current_dir = env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
-        
+    pub fn new(shell: ShellSession) -> Self {
         let os_type = if cfg!(target_os = "windows") {
             "windows".to_string()
         } else if cfg!(target_os = "macos") {
@@ -26,18 +26,22 @@ impl ContextManager {
             .unwrap_or_else(|_| "user".to_string());
         
         Self {
-            current_dir,
+            shell,
             os_type,
             username,
         }
     }
     
     pub fn get_system_context(&self) -> String {
-        format!(
+        let mut context = format!(
             "Operating System: {}\nCurrent Directory: {}\nUsername: {}",
             self.os_type,
-            self.current_dir.display(),
+            self.shell.current_dir().display(),
             self.username
-        )
+        );
+        if let Some(sandbox) = self.shell.sandbox() {
+            context.push_str(&format!("\nSandbox: {}", sandbox.describe()));
+        }
+        context
     }
 }
@@ -1,5 +1,15 @@
+use serde_json::Value;
+
+use crate::model_provider::{Content, FunctionCall, FunctionResponse, Part, Role, estimate_message_tokens};
+
+/// Number of most recent user turns that compaction never touches
+pub const KEEP_RECENT_TURNS: usize = 4;
+
+/// Tool outputs in older turns are cut down to this many characters
+const TRUNCATED_OUTPUT_CHARS: usize = 2_000;
+
 pub struct ConversationState {
-    messages: Vec<(String, String)>,
+    messages: Vec<Content>,
 }
 
 impl ConversationState {
@@ -9,19 +19,154 @@ impl ConversationState {
         }
     }
 
+    /// Create a conversation from previously saved messages
+    pub fn from_messages(messages: Vec<Content>) -> Self {
+        Self { messages }
+    }
+
     pub fn add_user_message(&mut self, message: &str) {
-        self.messages.push(("user".to_string(), message.to_string()));
+        self.push(Role::User, vec![Part::Text(message.to_string())]);
+    }
+
+    pub fn add_model_message(&mut self, message: &str) {
+        self.push(Role::Model, vec![Part::Text(message.to_string())]);
+    }
+
+    /// Record a model turn that requested one or more function calls,
+    /// along with any text the model produced before them.
+    pub fn add_function_calls(&mut self, text: &str, calls: &[FunctionCall]) {
+        let mut parts = Vec::new();
+        if !text.is_empty() {
+            parts.push(Part::Text(text.to_string()));
+        }
+        parts.extend(calls.iter().cloned().map(Part::FunctionCall));
+        self.push(Role::Model, parts);
     }
 
-    pub fn add_assistant_message(&mut self, message: &str) {
-        self.messages.push(("assistant".to_string(), message.to_string()));
+    /// Record the results of the function calls from the preceding model turn.
+    pub fn add_function_responses(&mut self, responses: Vec<FunctionResponse>) {
+        let parts = responses.into_iter().map(Part::FunctionResponse).collect();
+        self.push(Role::Function, parts);
     }
 
-    pub fn get_messages(&self) -> &[(String, String)] {
+    pub fn get_messages(&self) -> &[Content] {
         &self.messages
     }
 
     pub fn clear(&mut self) {
         self.messages.clear();
     }
+
+    /// Estimated number of tokens the history will consume
+    pub fn estimated_tokens(&self) -> usize {
+        self.messages.iter().map(estimate_message_tokens).sum()
+    }
+
+    /// Index of the first message that must be kept verbatim, i.e. the start
+    /// of the `keep_turns`-th most recent user turn. Everything before it may
+    /// be compacted. Returns 0 when there is nothing old enough to compact.
+    pub fn compaction_boundary(&self, keep_turns: usize) -> usize {
+        let turn_starts: Vec<usize> = self
+            .messages
+            .iter()
+            .enumerate()
+            .filter(|(_, message)| is_user_turn(message))
+            .map(|(i, _)| i)
+            .collect();
+
+        if turn_starts.len() <= keep_turns {
+            return 0;
+        }
+        turn_starts[turn_starts.len() - keep_turns]
+    }
+
+    /// Shorten tool outputs in the messages before `boundary`, keeping the
+    /// beginning and end of each. Returns whether anything was truncated.
+    pub fn truncate_tool_outputs(&mut self, boundary: usize) -> bool {
+        let mut truncated = false;
+
+        for message in &mut self.messages[..boundary] {
+            for part in &mut message.parts {
+                if let Part::FunctionResponse(response) = part {
+                    truncated |= truncate_value(&mut response.response);
+                }
+            }
+        }
+
+        truncated
+    }
+
+    /// Index of the message with the latest tool outputs, or the number of
+    /// messages if there are none
+    pub fn latest_tool_outputs(&self) -> usize {
+        self.messages
+            .iter()
+            .rposition(|message| message.parts.iter().any(|part| matches!(part, Part::FunctionResponse(_))))
+            .unwrap_or(self.messages.len())
+    }
+
+    /// Render the messages before `boundary` as a plain-text transcript,
+    /// suitable for asking the model to summarize them
+    pub fn transcript(&self, boundary: usize) -> String {
+        let mut transcript = String::new();
+
+        for message in &self.messages[..boundary] {
+            for part in &message.parts {
+                let line = match part {
+                    Part::Text(text) if message.role == Role::User => format!("User: {}", text),
+                    Part::Text(text) => format!("Assistant: {}", text),
+                    Part::FunctionCall(call) => format!("Assistant called {}({})", call.name, call.args),
+                    Part::FunctionResponse(response) => {
+                        let mut value = response.response.clone();
+                        truncate_value(&mut value);
+                        format!("Result of {}: {}", response.name, value)
+                    }
+                };
+                transcript.push_str(&line);
+                transcript.push_str("\n\n");
+            }
+        }
+
+        transcript
+    }
+
+    /// Replace the messages before `boundary` with a summary of them
+    pub fn replace_with_summary(&mut self, boundary: usize, summary: &str) {
+        let summary_turns = vec![
+            Content::text_message(Role::User, format!("Summary of the earlier conversation:\n\n{}", summary)),
+            Content::text_message(Role::Model, "Understood. I'll continue from this summary."),
+        ];
+
+        self.messages.splice(..boundary, summary_turns);
+    }
+
+    fn push(&mut self, role: Role, parts: Vec<Part>) {
+        self.messages.push(Content::new(role, parts));
+    }
+}
+
+/// Whether the message is a prompt written by the user. Sessions saved
+/// before roles were typed stored function results as user messages.
+fn is_user_turn(message: &Content) -> bool {
+    message.role == Role::User && message.parts.iter().any(|part| matches!(part, Part::Text(_)))
+}
+
+/// Truncate long strings anywhere inside a JSON value
+fn truncate_value(value: &mut Value) -> bool {
+    match value {
+        Value::String(text) => {
+            let char_count = text.chars().count();
+            if char_count <= TRUNCATED_OUTPUT_CHARS {
+                return false;
+            }
+            let half = TRUNCATED_OUTPUT_CHARS / 2;
+            let head: String = text.chars().take(half).collect();
+            let tail: String = text.chars().skip(char_count - half).collect();
+            *text = format!("{}\n... [{} characters truncated] ...\n{}", head, char_count - 2 * half, tail);
+            true
+        }
+        Value::Array(items) => items.iter_mut().fold(false, |acc, item| truncate_value(item) | acc),
+        Value::Object(map) => map.values_mut().fold(false, |acc, item| truncate_value(item) | acc),
+        _ => false,
+    }
 }
@@ -0,0 +1,119 @@
+use std::collections::HashSet;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use eyre::{Result, eyre};
+use rustyline::{Editor, Helper};
+
+use crate::config;
+
+/// Number of history entries kept when not configured
+pub const DEFAULT_MAX_SIZE: usize = 1000;
+
+/// File the REPL history is stored in, e.g.
+/// `~/.local/share/gemini-chat-cli/history`.
+///
+/// With `per_project` every project gets its own file, named after the
+/// directory containing the project configuration file, or the current
+/// directory if there is none, see `project_file_name`.
+pub fn history_path(per_project: bool) -> Result<PathBuf> {
+    let data_dir = dirs::data_dir().ok_or_else(|| eyre!("Could not determine the data directory"))?;
+    let dir = data_dir.join("gemini-chat-cli");
+
+    if !per_project {
+        return Ok(dir.join("history"));
+    }
+
+    let project_dir = match config::project_path() {
+        Some(path) => path.parent().map(Path::to_path_buf).unwrap_or_default(),
+        None => env::current_dir()?,
+    };
+
+    Ok(dir.join("projects").join(project_file_name(&project_dir)))
+}
+
+/// History file name of a project directory: the path with unsafe
+/// characters replaced, which keeps it recognizable, and a hash of the
+/// path, which keeps paths that only differ in those characters apart
+fn project_file_name(project_dir: &Path) -> String {
+    let path = project_dir.to_string_lossy();
+    let name: String = path
+        .chars()
+        .map(|c| if c.is_alphanumeric() || c == '-' || c == '.' { c } else { '_' })
+        .collect();
+
+    format!("{}-{:016x}.history", name, fnv1a(path.as_bytes()))
+}
+
+/// 64-bit FNV-1a hash, which unlike the standard library's hashers is
+/// guaranteed not to change between releases
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3))
+}
+
+/// Load the history into the editor, removing duplicate entries so that
+/// only the most recent use of each line is kept
+pub fn load<H: Helper>(editor: &mut Editor<H>, path: &Path) -> Result<()> {
+    if !path.exists() {
+        return Ok(());
+    }
+    editor.load_history(path)?;
+
+    let entries: Vec<String> = editor.history().iter().cloned().collect();
+    let mut seen = HashSet::new();
+    let mut unique: Vec<&String> = entries.iter().rev().filter(|entry| seen.insert(entry.as_str())).collect();
+    if unique.len() == entries.len() {
+        return Ok(());
+    }
+
+    unique.reverse();
+    editor.clear_history();
+    for entry in unique {
+        editor.add_history_entry(entry.as_str());
+    }
+    editor.save_history(path)?;
+
+    Ok(())
+}
+
+/// Add a line to the history and append it to the history file right away,
+/// so that concurrent sessions don't overwrite each other's entries
+pub fn add<H: Helper>(editor: &mut Editor<H>, path: Option<&Path>, line: &str) -> Result<()> {
+    if !editor.add_history_entry(line) {
+        return Ok(());
+    }
+
+    if let Some(path) = path {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        editor.append_history(path)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn project_file_names_keep_the_path_readable() {
+        let name = project_file_name(Path::new("/home/user/my.project"));
+        assert!(name.starts_with("_home_user_my.project-"), "{}", name);
+        assert!(name.ends_with(".history"), "{}", name);
+    }
+
+    #[test]
+    fn project_file_names_do_not_collide() {
+        assert_ne!(project_file_name(Path::new("/home/a_b/proj")), project_file_name(Path::new("/home/a/b/proj")));
+        assert_eq!(project_file_name(Path::new("/home/a/b/proj")), project_file_name(Path::new("/home/a/b/proj")));
+    }
+
+    #[test]
+    fn fnv1a_matches_reference_values() {
+        assert_eq!(fnv1a(b""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(fnv1a(b"a"), 0xaf63_dc4c_8601_ec8c);
+    }
+}
@@ -1 +1,257 @@
-// Placeholder for input source implementation
+use std::collections::VecDeque;
+use std::env;
+use std::fs;
+use std::io::{self, Read};
+use std::path::PathBuf;
+use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use eyre::{Result, bail, eyre};
+use rustyline::error::ReadlineError;
+use rustyline::Editor;
+use tracing::warn;
+
+use super::history;
+use super::prompt::{self, ChatHelper};
+use crate::config::HistorySettings;
+
+/// Opens and closes a multi-line message, e.g.
+///
+/// ```text
+/// > """
+/// Why does this panic?
+///
+/// thread 'main' panicked at src/main.rs:4:5
+/// """
+/// ```
+pub const TRIPLE_QUOTE: &str = "\"\"\"";
+
+/// Where the lines of user input come from.
+///
+/// Every input is a complete message. On a terminal, lines continued with a
+/// trailing backslash are joined and a `"""` block is returned without its
+/// quotes. Scripted input is returned as is.
+pub struct InputSource(Inner);
+
+enum Inner {
+    /// An interactive terminal, with completion and history
+    Readline {
+        editor: Box<Editor<ChatHelper>>,
+        history_path: Option<PathBuf>,
+    },
+    /// A fixed list of inputs, such as a prompt given on the command line
+    /// or piped through stdin
+    Lines(VecDeque<String>),
+}
+
+impl InputSource {
+    /// Read input from the terminal, with the history configured in `settings`
+    pub fn readline(settings: &HistorySettings) -> Result<Self> {
+        let mut editor = prompt::rl(settings.max_size.unwrap_or(history::DEFAULT_MAX_SIZE))?;
+
+        // History is a convenience, so problems with it are reported but not fatal
+        let history_path = match history::history_path(settings.per_project.unwrap_or(false)) {
+            Ok(path) => Some(path),
+            Err(e) => {
+                warn!("History is disabled: {}", e);
+                None
+            }
+        };
+        if let Some(path) = &history_path {
+            if let Err(e) = history::load(&mut editor, path) {
+                warn!("Failed to load history from {}: {}", path.display(), e);
+            }
+        }
+
+        Ok(Self(Inner::Readline {
+            editor: Box::new(editor),
+            history_path,
+        }))
+    }
+
+    /// Read the given inputs in order, without a terminal. This is also how
+    /// tests drive a `ChatContext`.
+    pub fn lines<I, S>(lines: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        Self(Inner::Lines(lines.into_iter().map(Into::into).collect()))
+    }
+
+    /// Read the next input, or `None` at the end of input (Ctrl-D).
+    ///
+    /// Ctrl-C discards the line being edited and returns an empty input.
+    pub fn read_line(&mut self, prompt: &str) -> Result<Option<String>> {
+        match &mut self.0 {
+            Inner::Readline { editor, history_path } => {
+                let line = match editor.readline(prompt) {
+                    Ok(line) => line,
+                    Err(ReadlineError::Interrupted) => return Ok(Some(String::new())),
+                    Err(ReadlineError::Eof) => return Ok(None),
+                    Err(e) => return Err(e.into()),
+                };
+
+                if !line.trim().is_empty() {
+                    if let Err(e) = history::add(editor, history_path.as_deref(), &line) {
+                        warn!("Failed to save history: {}", e);
+                    }
+                }
+
+                Ok(Some(normalize(&line)))
+            }
+            Inner::Lines(lines) => Ok(lines.pop_front()),
+        }
+    }
+
+    /// Read the answer to a question, such as whether a tool may run.
+    /// Answers aren't kept in the history.
+    pub fn read_answer(&mut self) -> Result<Option<String>> {
+        match &mut self.0 {
+            Inner::Readline { .. } => {
+                let mut answer = String::new();
+                if io::stdin().read_line(&mut answer)? == 0 {
+                    return Ok(None);
+                }
+                Ok(Some(answer))
+            }
+            Inner::Lines(lines) => Ok(lines.pop_front()),
+        }
+    }
+}
+
+/// The prompt for a non-interactive run when stdin is not a terminal. Piped
+/// content is attached to the prompt given with `--input`, or is the whole
+/// prompt without one.
+pub fn prompt_with_stdin(prompt: Option<String>) -> Result<String> {
+    let mut content = String::new();
+    io::stdin().read_to_string(&mut content)?;
+    let content = content.trim_end();
+
+    match prompt {
+        Some(prompt) if content.trim().is_empty() => Ok(prompt),
+        Some(prompt) => {
+            // The fence must be longer than any run of backticks in the content
+            let longest_run = content
+                .split(|c| c != '`')
+                .map(str::len)
+                .max()
+                .unwrap_or(0);
+            let fence = "`".repeat(longest_run.max(2) + 1);
+            Ok(format!("{}\n\n{}\n{}\n{}", prompt, fence, content, fence))
+        }
+        None if content.trim().is_empty() => {
+            bail!("Nothing to send: stdin is empty. Pass a prompt with --input or pipe one in.")
+        }
+        None => Ok(content.to_string()),
+    }
+}
+
+/// Whether `input` continues on the next line: after a trailing backslash,
+/// inside an unclosed code fence and inside an unclosed `"""` block
+pub fn is_incomplete(input: &str) -> bool {
+    if let Some(body) = input.trim_start().strip_prefix(TRIPLE_QUOTE) {
+        return !body.trim_end().ends_with(TRIPLE_QUOTE);
+    }
+
+    let fences = input
+        .lines()
+        .filter(|line| line.trim_start().starts_with("```"))
+        .count();
+
+    input.ends_with('\\') || fences % 2 == 1
+}
+
+/// Turn raw multi-line input into the message it stands for. The text of a
+/// `"""` block is kept verbatim, otherwise backslash continuations are joined.
+/// Shell commands are left as they are, as bash joins continuations itself.
+fn normalize(input: &str) -> String {
+    let trimmed = input.trim();
+    if let Some(body) = trimmed
+        .strip_prefix(TRIPLE_QUOTE)
+        .and_then(|body| body.strip_suffix(TRIPLE_QUOTE))
+    {
+        return body.trim_matches('\n').to_string();
+    }
+
+    if trimmed.starts_with('!') {
+        return input.to_string();
+    }
+
+    input.replace("\\\n", "\n")
+}
+
+/// Compose a message in the user's editor (`$VISUAL`, `$EDITOR` or `vi`).
+///
+/// Returns `None` when the file is left empty, so that closing the editor
+/// without writing anything cancels the message.
+pub fn compose_in_editor() -> Result<Option<String>> {
+    let editor = env::var("VISUAL")
+        .or_else(|_| env::var("EDITOR"))
+        .ok()
+        .filter(|editor| !editor.trim().is_empty())
+        .unwrap_or_else(|| String::from("vi"));
+
+    // The editor may be configured with arguments, e.g. `code --wait`
+    let mut words = editor.split_whitespace();
+    let program = words.next().ok_or_else(|| eyre!("No editor configured"))?;
+    let arguments: Vec<&str> = words.collect();
+
+    let nanos = SystemTime::now().duration_since(UNIX_EPOCH)?.as_nanos();
+    let path = env::temp_dir().join(format!("gemini-chat-{}-{}.md", std::process::id(), nanos));
+    fs::write(&path, "")?;
+
+    let status = Command::new(program).args(&arguments).arg(&path).status();
+    let contents = fs::read_to_string(&path);
+    let _ = fs::remove_file(&path);
+
+    let status = status.map_err(|e| eyre!("Failed to start the editor {}: {}", program, e))?;
+    if !status.success() {
+        bail!("The editor {} exited with {}, nothing was sent", program, status);
+    }
+
+    let contents = contents?;
+    let message = contents.trim();
+    Ok((!message.is_empty()).then(|| message.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn continues_unfinished_input() {
+        assert!(is_incomplete("\"\"\""));
+        assert!(is_incomplete("\"\"\"\nfirst line\n"));
+        assert!(is_incomplete("cargo build \\"));
+        assert!(is_incomplete("Why does this fail?\n```rust\nfn main() {}"));
+
+        assert!(!is_incomplete("hello"));
+        assert!(!is_incomplete("\"\"\"\nfirst line\n\"\"\""));
+        assert!(!is_incomplete("Why does this fail?\n```rust\nfn main() {}\n```"));
+    }
+
+    #[test]
+    fn returns_quoted_blocks_verbatim() {
+        assert_eq!(normalize("\"\"\"\nline \\\n  indented\n\"\"\""), "line \\\n  indented");
+    }
+
+    #[test]
+    fn joins_continued_lines_of_messages() {
+        assert_eq!(normalize("first \\\nsecond"), "first \nsecond");
+        assert_eq!(normalize("single line"), "single line");
+    }
+
+    #[test]
+    fn leaves_continued_shell_commands_to_bash() {
+        assert_eq!(normalize("!cargo build \\\n  --release"), "!cargo build \\\n  --release");
+    }
+
+    #[test]
+    fn reads_scripted_lines_in_order() {
+        let mut input = InputSource::lines(["/help", "y"]);
+        assert_eq!(input.read_line("> ").unwrap().as_deref(), Some("/help"));
+        assert_eq!(input.read_answer().unwrap().as_deref(), Some("y"));
+        assert_eq!(input.read_line("> ").unwrap(), None);
+    }
+}
@@ -0,0 +1,156 @@
+use std::collections::VecDeque;
+use std::io::{self, Write};
+use std::time::Duration;
+
+use crossterm::style::Stylize;
+use crossterm::terminal::{self, ClearType};
+use crossterm::{cursor, queue};
+
+use super::tools::execute_bash::CommandEvent;
+
+/// Number of the most recent lines of a running command that are shown
+const WINDOW_LINES: usize = 10;
+
+/// The output of a running shell command, shown as it arrives.
+///
+/// On a terminal the last `WINDOW_LINES` lines are shown dimmed and redrawn
+/// in place, so that a long build doesn't scroll everything else away.
+/// Elsewhere the lines aren't shown and only the summary is printed.
+#[derive(Debug)]
+pub struct LiveOutput {
+    terminal: bool,
+    width: usize,
+    lines: VecDeque<String>,
+    /// Lines that scrolled out of the window
+    hidden: usize,
+    /// Rows currently on screen, which the next redraw replaces
+    drawn: usize,
+    /// Exit code and run time, once the command has exited
+    exit: Option<(Option<i32>, Duration)>,
+    /// Why the sandbox blocked the command, if it did
+    blocked: Option<String>,
+}
+
+impl LiveOutput {
+    pub fn new(terminal: bool) -> Self {
+        let width = terminal::size().map(|(columns, _)| columns as usize).unwrap_or(80);
+        Self {
+            terminal,
+            width,
+            lines: VecDeque::new(),
+            hidden: 0,
+            drawn: 0,
+            exit: None,
+            blocked: None,
+        }
+    }
+
+    /// Record an event without redrawing, see `draw`
+    pub fn push(&mut self, event: CommandEvent) {
+        match event {
+            CommandEvent::Line(line) => {
+                self.lines.push_back(sanitize(&line));
+                if self.lines.len() > WINDOW_LINES {
+                    self.lines.pop_front();
+                    self.hidden += 1;
+                }
+            }
+            CommandEvent::Blocked(reason) => self.blocked = Some(reason),
+            CommandEvent::Exited { code, elapsed } => self.exit = Some((code, elapsed)),
+        }
+    }
+
+    /// Redraw the window with the latest lines
+    pub fn draw(&mut self, output: &mut dyn Write) -> io::Result<()> {
+        if !self.terminal || self.lines.is_empty() {
+            return Ok(());
+        }
+
+        self.clear(output)?;
+
+        let mut rows = Vec::new();
+        if self.hidden > 0 {
+            rows.push(format!("… {} earlier lines", self.hidden));
+        }
+        rows.extend(self.lines.iter().cloned());
+
+        // Rows must not wrap, or moving back up to redraw them goes wrong
+        let width = self.width.saturating_sub(1).max(1);
+        for row in &rows {
+            let row: String = row.chars().take(width).collect();
+            writeln!(output, "{}", row.dim())?;
+        }
+        self.drawn = rows.len();
+
+        output.flush()
+    }
+
+    /// Remove the window from the screen
+    pub fn clear(&mut self, mut output: &mut dyn Write) -> io::Result<()> {
+        if self.drawn > 0 {
+            queue!(
+                &mut output,
+                cursor::MoveToPreviousLine(self.drawn as u16),
+                terminal::Clear(ClearType::FromCursorDown)
+            )?;
+            self.drawn = 0;
+        }
+        output.flush()
+    }
+
+    /// Print how the command exited and how long it ran, and why the
+    /// sandbox blocked it
+    pub fn summary(&self, output: &mut dyn Write) -> io::Result<()> {
+        let Some((code, elapsed)) = self.exit else {
+            return Ok(());
+        };
+
+        let summary = match code {
+            Some(code) => format!("Exited with code {} after {:.1}s", code, elapsed.as_secs_f64()),
+            None => format!("Terminated by a signal after {:.1}s", elapsed.as_secs_f64()),
+        };
+
+        if !self.terminal {
+            writeln!(output, "{}", summary)?;
+        } else if code == Some(0) {
+            writeln!(output, "{}", summary.dim())?;
+        } else {
+            writeln!(output, "{}", summary.red())?;
+        }
+
+        if let Some(reason) = &self.blocked {
+            if self.terminal {
+                writeln!(output, "{}", reason.as_str().yellow())?;
+            } else {
+                writeln!(output, "{}", reason)?;
+            }
+        }
+        output.flush()
+    }
+}
+
+/// Make a line of command output safe to redraw: only the text after the
+/// last carriage return is kept, as progress bars overwrite themselves,
+/// and escape sequences and other control characters are removed.
+fn sanitize(line: &str) -> String {
+    let line = line.trim_end_matches('\r').rsplit('\r').next().unwrap_or_default();
+
+    let mut result = String::with_capacity(line.len());
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\x1b' => {
+                // Skip to the final byte of the sequence, e.g. the `m` of `\x1b[31m`
+                for c in chars.by_ref() {
+                    if c.is_ascii_alphabetic() {
+                        break;
+                    }
+                }
+            }
+            '\t' => result.push_str("    "),
+            c if c.is_control() => {}
+            c => result.push(c),
+        }
+    }
+    result
+}
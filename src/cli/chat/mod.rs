@@ -1,28 +1,50 @@
 pub mod command;
 pub mod context;
 pub mod conversation_state;
+pub mod history;
 pub mod input_source;
+pub mod live_output;
 pub mod parse;
 pub mod parser;
 pub mod prompt;
+pub mod session;
 pub mod tools;
 
-use std::io::Write;
+use std::collections::HashSet;
+use std::future::Future;
+use std::io::{self, IsTerminal, Write};
 use std::process::ExitCode;
 
-use command::Command;
+use command::{Command, CommandSpec, COMMANDS};
 use context::ContextManager;
-use conversation_state::ConversationState;
-use eyre::{Result, bail};
+use conversation_state::{ConversationState, KEEP_RECENT_TURNS};
+use eyre::{Result, bail, eyre};
+use futures::StreamExt;
+use input_source::InputSource;
+use live_output::LiveOutput;
+use parse::ParseState;
 use prompt::generate_prompt;
-use regex::Regex;
 use serde_json::{json, Value};
-use tracing::error;
-
-use crate::cli::chat::tools::execute_bash;
-use crate::cli::chat::tools::fs_read;
-use crate::cli::chat::tools::fs_write;
-use crate::gemini_client::{GeminiClient, ToolDefinition};
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+
+use crate::cli::chat::tools::execute_bash::{self, CommandEvent};
+use crate::cli::chat::tools::shell_session::ShellSession;
+use crate::cli::chat::tools::{Tool, ToolContext, ToolRegistry};
+use crate::config::{self, Config};
+use crate::model_provider::{
+    Content,
+    FunctionCall,
+    FunctionResponse,
+    ModelError,
+    ModelProvider,
+    ModelResponse,
+    Role,
+    ToolDefinition,
+    create_provider,
+    estimate_tokens,
+    estimate_tool_tokens,
+};
+use crate::retry::RetryNotice;
 
 const WELCOME_TEXT: &str = "
 Hi, I'm Gemini Chat. Ask me anything.
@@ -37,396 +59,533 @@ Things to try
 /quit         Quit the application
 ";
 
-const HELP_TEXT: &str = "
-Gemini Chat CLI
+const SUMMARY_PROMPT: &str = "You summarize conversations between a user and a coding assistant \
+that uses tools on the user's machine. Write a concise summary that preserves the user's goals, \
+decisions made, important facts discovered (file paths, commands, errors, results) and any open \
+tasks. Omit pleasantries. The summary replaces the original messages, so include everything \
+needed to continue the work.";
 
-/clear        Clear the conversation history
-/help         Show this help dialogue
-/quit         Quit the application
-
-!{command}    Quickly execute a command in your current session
-";
+/// How many times the model is asked to retry after a malformed function call
+const MAX_MALFORMED_CALL_RETRIES: usize = 2;
 
 pub struct ChatContext {
     output: Box<dyn Write>,
-    input: Option<String>,
+    input_source: InputSource,
     interactive: bool,
+    /// Whether model output is rendered as markdown, which is only done
+    /// when stdout is a terminal
+    render_markdown: bool,
     conversation_state: ConversationState,
+    /// Name the conversation is saved under, once saved or loaded
+    session_name: Option<String>,
     context_manager: Option<ContextManager>,
-    accept_all: bool,
-    gemini_client: Option<GeminiClient>,
+    /// Effective configuration from config files and command line flags
+    config: Config,
+    /// Tools the user has approved for the rest of the session
+    trusted_tools: HashSet<String>,
+    tool_registry: ToolRegistry,
+    tool_context: ToolContext,
+    provider: Option<Box<dyn ModelProvider>>,
+    /// Notices from the provider about failed requests it is about to retry
+    retry_notices: UnboundedReceiver<RetryNotice>,
+    retry_notifier: UnboundedSender<RetryNotice>,
+    /// Output of the shell commands run by `tool_context`, as it arrives
+    command_events: UnboundedReceiver<CommandEvent>,
 }
 
 impl ChatContext {
     pub fn new(
         output: Box<dyn Write>,
-        input: Option<String>,
+        input_source: InputSource,
         interactive: bool,
-        accept_all: bool,
-    ) -> Self {
-        Self {
+        config: Config,
+    ) -> Result<Self> {
+        let mut tool_registry = ToolRegistry::new()?;
+        if let Some(enabled) = &config.tools.enabled {
+            tool_registry.retain(enabled)?;
+        }
+
+        let trusted_tools = config.approval.trusted_tools.iter().flatten().cloned().collect();
+        let (retry_notifier, retry_notices) = mpsc::unbounded_channel();
+        let (command_sender, command_events) = mpsc::unbounded_channel();
+        let sandbox = config.sandbox_policy()?;
+        if let Some(sandbox) = &sandbox {
+            sandbox.check()?;
+        }
+        let shell = ShellSession::new(sandbox);
+        let tool_context = ToolContext {
+            bash_timeout: config.bash_timeout(),
+            events: Some(command_sender),
+            shell: shell.clone(),
+        };
+
+        Ok(Self {
             output,
-            input,
+            input_source,
             interactive,
+            render_markdown: io::stdout().is_terminal(),
             conversation_state: ConversationState::new(),
-            context_manager: Some(ContextManager::new()),
-            accept_all,
-            gemini_client: None,
-        }
+            session_name: None,
+            context_manager: Some(ContextManager::new(shell)),
+            config,
+            trusted_tools,
+            tool_registry,
+            tool_context,
+            provider: None,
+            retry_notices,
+            retry_notifier,
+            command_events,
+        })
     }
 
     pub async fn run(&mut self) -> Result<ExitCode> {
-        // Initialize Gemini client
-        self.gemini_client = match GeminiClient::new() {
-            Ok(client) => Some(client),
-            Err(e) => {
-                writeln!(self.output, "Failed to initialize Gemini client: {}", e)?;
-                return Ok(ExitCode::FAILURE);
-            }
-        };
+        // Initialize the model provider
+        if let Err(e) = self.init_provider() {
+            writeln!(self.output, "Failed to initialize model provider: {}", e)?;
+            return Ok(ExitCode::FAILURE);
+        }
 
         if self.interactive {
             self.print_welcome()?;
         }
 
-        // Handle non-interactive mode (single query)
-        if let Some(input) = self.input.take() {
-            self.handle_input(&input).await?;
-            return Ok(ExitCode::SUCCESS);
-        }
-
-        // Interactive mode
-        if self.interactive {
-            self.run_interactive().await?;
-        }
+        self.process_inputs().await?;
 
         Ok(ExitCode::SUCCESS)
     }
 
+    /// Create the model provider from the current configuration, replacing
+    /// the existing one only if that succeeds
+    fn init_provider(&mut self) -> Result<()> {
+        let mut provider = create_provider(
+            self.config.provider(),
+            self.config.model.clone(),
+            self.config.base_url.clone(),
+            self.config.generation.clone(),
+            self.config.retry_policy(),
+        )?;
+        provider.set_retry_notifier(self.retry_notifier.clone());
+        self.provider = Some(provider);
+        Ok(())
+    }
+
+    /// Resume a saved session before the chat starts. Without a name the
+    /// most recently saved session is resumed.
+    pub fn resume(&mut self, name: Option<&str>) -> Result<()> {
+        let name = match name {
+            Some(name) => name.to_string(),
+            None => session::latest()?.ok_or_else(|| eyre!("No saved sessions to resume"))?,
+        };
+        self.load_session(&name)
+    }
+
     fn print_welcome(&mut self) -> Result<()> {
         writeln!(self.output, "{}", WELCOME_TEXT)?;
         Ok(())
     }
 
-    async fn run_interactive(&mut self) -> Result<()> {
-        let mut rl = prompt::rl()?;
-        
+    /// Handle inputs until the input source runs out or the user quits. In
+    /// a non-interactive run the first error ends the run.
+    async fn process_inputs(&mut self) -> Result<()> {
         loop {
             let prompt_text = generate_prompt(None);
-            let readline = rl.readline(&prompt_text);
+            let Some(line) = self.input_source.read_line(&prompt_text)? else {
+                break;
+            };
             
-            match readline {
-                Ok(line) => {
-                    if line.trim().is_empty() {
-                        continue;
-                    }
-                    
-                    rl.add_history_entry(line.as_str());
-                    
-                    if line.trim() == "/quit" {
-                        break;
-                    }
-                    
-                    if let Err(e) = self.handle_input(&line).await {
-                        writeln!(self.output, "Error: {}", e)?;
-                    }
-                }
-                Err(e) => {
-                    writeln!(self.output, "Error: {}", e)?;
-                    break;
-                }
+            if line.trim().is_empty() {
+                continue;
+            }
+            
+            match self.handle_input(&line).await {
+                Ok(true) => {}
+                Ok(false) => break,
+                Err(e) if self.interactive => writeln!(self.output, "Error: {}", e)?,
+                Err(e) => return Err(e),
+            }
+        }
+        
+        // Keep the conversation so it can be resumed later, under a generated
+        // name unless it has one. Old generated sessions are cleaned up.
+        if self.interactive && !self.conversation_state.get_messages().is_empty() {
+            self.save_session(None)?;
+            if let Err(e) = session::prune_generated(session::MAX_GENERATED) {
+                writeln!(self.output, "Failed to clean up old sessions: {}", e)?;
             }
         }
         
         Ok(())
     }
 
-    async fn handle_input(&mut self, input: &str) -> Result<()> {
-        match input.trim() {
-            "/help" => {
-                writeln!(self.output, "{}", HELP_TEXT)?;
+    /// Parse and run a line of input. Returns `false` if the user asked to
+    /// quit.
+    async fn handle_input(&mut self, input: &str) -> Result<bool> {
+        let command = match parser::parse_command(input) {
+            Ok(command) => command,
+            Err(e) => {
+                writeln!(self.output, "{}", e)?;
+                return Ok(true);
             }
-            "/clear" => {
-                self.conversation_state = ConversationState::new();
+        };
+        
+        match command {
+            Command::Help { command } => {
+                self.show_help(command.as_deref())?;
+            }
+            Command::Clear => {
+                self.conversation_state.clear();
+                self.session_name = None;
                 writeln!(self.output, "Conversation cleared.")?;
             }
-            _ => {
-                if input.starts_with('!') {
-                    // Handle shell command
-                    let cmd = &input[1..];
-                    let result = execute_bash::execute_bash(cmd).await?;
-                    writeln!(self.output, "{}", result)?;
-                } else {
-                    // Handle normal chat input
-                    self.process_chat_input(input).await?;
+            Command::Quit => {
+                return Ok(false);
+            }
+            Command::Save { name } => {
+                self.save_session(name.as_deref())?;
+            }
+            Command::Load { name } => {
+                self.load_session(&name)?;
+            }
+            Command::Sessions => {
+                self.list_sessions()?;
+            }
+            Command::Compact => {
+                self.compact(true).await?;
+            }
+            Command::Config => {
+                self.show_config()?;
+            }
+            Command::Model { name } => {
+                self.switch_model(name)?;
+            }
+            Command::Editor => {
+                match input_source::compose_in_editor()? {
+                    Some(message) => {
+                        writeln!(self.output, "{}", message)?;
+                        self.process_chat_input(&message).await?;
+                    }
+                    None => writeln!(self.output, "The message is empty, nothing was sent.")?,
+                }
+            }
+            Command::ShellReset => {
+                self.tool_context.shell.reset().await;
+                writeln!(self.output, "The shell was reset to {}.", self.tool_context.shell.current_dir().display())?;
+            }
+            Command::ShellCommand(command) => {
+                let mut live = LiveOutput::new(io::stdout().is_terminal());
+                let result = with_command_events(
+                    &mut self.output,
+                    &mut self.command_events,
+                    &mut live,
+                    execute_bash::execute_bash(&command, &self.tool_context),
+                )
+                .await;
+                
+                // The full output replaces the window of its last lines
+                live.clear(&mut self.output)?;
+                let output = result?.combined();
+                write!(self.output, "{}", output)?;
+                if !output.is_empty() && !output.ends_with('\n') {
+                    writeln!(self.output)?;
                 }
+                live.summary(&mut self.output)?;
+            }
+            Command::ChatMessage(message) => {
+                self.process_chat_input(&message).await?;
             }
         }
         
-        Ok(())
+        Ok(true)
     }
 
-    async fn process_chat_input(&mut self, input: &str) -> Result<()> {
-        // Add user message to conversation state
-        self.conversation_state.add_user_message(input);
-        
-        // Get response from Gemini API
-        let response = self.get_gemini_response().await?;
-        
-        // Display response
-        self.display_response(&response).await?;
+    fn show_help(&mut self, command: Option<&str>) -> Result<()> {
+        let Some(name) = command else {
+            writeln!(self.output, "{}", help_text())?;
+            return Ok(());
+        };
         
+        match CommandSpec::find(name) {
+            Some(spec) => writeln!(self.output, "{}\n\n{}", spec.usage, spec.description)?,
+            None => writeln!(self.output, "Unknown command /{}. Type /help to see the available commands.", name)?,
+        }
         Ok(())
     }
 
-    async fn display_response(&mut self, response: &str) -> Result<()> {
-        // Check if the response contains tool calls
-        if let Some((text, tool_calls)) = self.extract_tool_calls(response) {
-            // Display the text part
-            if !text.trim().is_empty() {
-                writeln!(self.output, "{}", text)?;
+    /// Show the current model, or switch to another model of the same
+    /// provider while keeping the conversation
+    fn switch_model(&mut self, name: Option<String>) -> Result<()> {
+        let Some(name) = name else {
+            let provider = self.provider.as_ref().map(|p| p.name()).unwrap_or_default();
+            writeln!(self.output, "Current model: {}", provider)?;
+            return Ok(());
+        };
+        
+        let previous = self.config.model.replace(name);
+        match self.init_provider() {
+            Ok(()) => {
+                let provider = self.provider.as_ref().map(|p| p.name()).unwrap_or_default();
+                writeln!(self.output, "Switched to {}.", provider)?;
             }
-            
-            // Process tool calls
-            for tool_call in tool_calls {
-                // Execute the tool call
-                let result = match self.execute_tool_call(&tool_call).await {
-                    Ok(res) => res,
-                    Err(e) => {
-                        let error_msg = format!("Error executing tool call: {}", e);
-                        writeln!(self.output, "{}", error_msg)?;
-                        error_msg
-                    }
-                };
-                
-                // Add tool call and result to conversation
-                self.conversation_state.add_assistant_message(&format!("Tool call: {}", tool_call));
-                self.conversation_state.add_user_message(&format!("Tool result: {}", result));
-                
-                // Get follow-up response from Gemini
-                let follow_up = self.get_gemini_response().await?;
-                
-                // Check if follow-up response also contains tool calls
-                if let Some((follow_text, follow_tool_calls)) = self.extract_tool_calls(&follow_up) {
-                    if !follow_text.trim().is_empty() {
-                        writeln!(self.output, "{}", follow_text)?;
-                    }
-                    
-                    // Process nested tool calls recursively (limited to one level of nesting)
-                    for follow_tool_call in follow_tool_calls {
-                        let follow_result = self.execute_tool_call(&follow_tool_call).await?;
-                        
-                        // Add nested tool call and result to conversation
-                        self.conversation_state.add_assistant_message(&format!("Tool call: {}", follow_tool_call));
-                        self.conversation_state.add_user_message(&format!("Tool result: {}", follow_result));
-                        
-                        // Get final response after nested tool call
-                        let final_response = self.get_gemini_response().await?;
-                        writeln!(self.output, "{}", final_response)?;
-                        self.conversation_state.add_assistant_message(&final_response);
-                    }
-                } else {
-                    // No nested tool calls, display the follow-up response
-                    writeln!(self.output, "{}", follow_up)?;
-                    self.conversation_state.add_assistant_message(&follow_up);
-                }
+            Err(e) => {
+                self.config.model = previous;
+                writeln!(self.output, "Failed to switch model: {}", e)?;
             }
-        } else {
-            // Regular response, just display it
-            writeln!(self.output, "{}", response)?;
-            self.conversation_state.add_assistant_message(response);
         }
-        
         Ok(())
     }
 
-    fn extract_tool_calls(&self, response: &str) -> Option<(String, Vec<String>)> {
-        // First try to extract XML-style function calls
-        let xml_result = self.extract_xml_tool_calls(response);
-        if xml_result.is_some() {
-            return xml_result;
+    fn show_config(&mut self) -> Result<()> {
+        let config = &self.config;
+        let not_set = |default: &str| format!("(default: {})", default);
+
+        let mut lines = Vec::new();
+        lines.push(String::from("Configuration files:"));
+        if config.sources.is_empty() {
+            lines.push(String::from("  (none)"));
+        }
+        for source in &config.sources {
+            lines.push(format!("  {}", source.display()));
+        }
+        if let Some(path) = config::global_path() {
+            lines.push(format!("Global config path: {}", path.display()));
+        }
+        lines.push(format!("Project config file: {}", config::PROJECT_CONFIG_FILE));
+        lines.push(String::new());
+
+        let provider = self.provider.as_ref().map(|p| p.name()).unwrap_or_default();
+        let mut trusted_tools: Vec<String> = self.trusted_tools.iter().cloned().collect();
+        trusted_tools.sort();
+        let generation = &config.generation;
+        let values = [
+            ("provider", format!("{:?}", config.provider()).to_lowercase()),
+            ("model", config.model.clone().unwrap_or_else(|| not_set(&provider))),
+            ("base_url", config.base_url.clone().unwrap_or_else(|| not_set("provider"))),
+            ("max_steps", config.max_steps().to_string()),
+            ("context_budget", self.context_budget().to_string()),
+            ("generation.temperature", generation.temperature.map(|v| v.to_string()).unwrap_or_else(|| not_set("provider"))),
+            ("generation.top_p", generation.top_p.map(|v| v.to_string()).unwrap_or_else(|| not_set("provider"))),
+            ("generation.top_k", generation.top_k.map(|v| v.to_string()).unwrap_or_else(|| not_set("provider"))),
+            ("generation.max_output_tokens", generation.max_output_tokens.map(|v| v.to_string()).unwrap_or_else(|| not_set("provider"))),
+            ("tools.enabled", self.tool_registry.names().join(", ")),
+            ("tools.bash_timeout_secs", config.bash_timeout().as_secs().to_string()),
+            ("approval.accept_all", config.accept_all().to_string()),
+            ("approval.trusted_tools", trusted_tools.join(", ")),
+            ("prompt.additions", config.prompt.additions.clone().unwrap_or_else(|| String::from("(none)"))),
+            ("network.max_attempts", config.retry_policy().max_attempts.to_string()),
+            ("network.timeout_secs", config.retry_policy().timeout.as_secs().to_string()),
+            ("history.max_size", config.history.max_size.unwrap_or(history::DEFAULT_MAX_SIZE).to_string()),
+            ("history.per_project", config.history.per_project.unwrap_or(false).to_string()),
+            ("sandbox.enabled", config.sandbox.enabled.unwrap_or(false).to_string()),
+            ("sandbox.network", config.sandbox.network.unwrap_or(false).to_string()),
+            ("sandbox.writable_paths", config.sandbox.writable_paths.iter().flatten().cloned().collect::<Vec<_>>().join(", ")),
+        ];
+
+        for (key, value) in values {
+            lines.push(format!("{:<30} {}", key, value));
         }
+
+        writeln!(self.output, "{}", lines.join("\n"))?;
+        Ok(())
+    }
+
+    fn save_session(&mut self, name: Option<&str>) -> Result<()> {
+        let name = name
+            .map(str::to_string)
+            .or_else(|| self.session_name.clone())
+            .unwrap_or_else(session::generate_name);
         
-        // If no XML-style function calls found, try to extract JSON-style tool calls
-        self.extract_json_tool_calls(response)
+        let path = session::save(&name, self.conversation_state.get_messages())?;
+        writeln!(self.output, "Session saved as '{}' ({})", name, path.display())?;
+        self.session_name = Some(name);
+        
+        Ok(())
     }
-    
-    fn extract_xml_tool_calls(&self, response: &str) -> Option<(String, Vec<String>)> {
-        // Regular expression to extract tool calls in XML format
-        let re = Regex::new(r#"<function_calls>([\s\S]*?)</function_calls>"#).ok()?;
+
+    fn load_session(&mut self, name: &str) -> Result<()> {
+        let session = session::load(name)?;
+        let message_count = session.messages.len();
         
-        if let Some(captures) = re.captures(response) {
-            let tool_call_block = captures.get(1)?.as_str();
-            
-            // Extract individual tool calls
-            let tool_re = Regex::new(r#"<invoke name="([^"]+)">([\s\S]*?)</invoke>"#).ok()?;
-            let mut tool_calls = Vec::new();
-            
-            for tool_match in tool_re.captures_iter(tool_call_block) {
-                let tool_name = tool_match.get(1)?.as_str();
-                let tool_params = tool_match.get(2)?.as_str();
-                
-                // Format the tool call as JSON
-                let mut params_map = serde_json::Map::new();
-                
-                // Extract parameters
-                let param_re = Regex::new(r#"<parameter name="([^"]+)">([^<]*)</parameter>"#).ok()?;
-                for param_match in param_re.captures_iter(tool_params) {
-                    let param_name = param_match.get(1)?.as_str();
-                    let param_value = param_match.get(2)?.as_str();
-                    params_map.insert(param_name.to_string(), Value::String(param_value.to_string()));
-                }
-                
-                let tool_call_json = json!({
-                    "name": tool_name,
-                    "parameters": params_map
-                });
-                
-                tool_calls.push(tool_call_json.to_string());
-            }
-            
-            // Get the text part (everything before the first tool call)
-            let text_part = response.split("<function_calls>").next().unwrap_or("").trim();
-            
-            return Some((text_part.to_string(), tool_calls));
+        self.conversation_state = ConversationState::from_messages(session.messages);
+        self.session_name = Some(session.name);
+        
+        writeln!(
+            self.output,
+            "Loaded session '{}' ({} messages, saved {})",
+            name,
+            message_count,
+            session.saved_at.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M")
+        )?;
+        
+        Ok(())
+    }
+
+    fn list_sessions(&mut self) -> Result<()> {
+        let sessions = session::list()?;
+        if sessions.is_empty() {
+            writeln!(self.output, "No saved sessions.")?;
+            return Ok(());
+        }
+        
+        for info in sessions {
+            let marker = if self.session_name.as_deref() == Some(info.name.as_str()) { "*" } else { " " };
+            writeln!(
+                self.output,
+                "{} {:<30} {}  {} messages",
+                marker,
+                info.name,
+                info.saved_at.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M"),
+                info.message_count
+            )?;
         }
         
-        None
+        Ok(())
     }
-    
-    fn extract_json_tool_calls(&self, response: &str) -> Option<(String, Vec<String>)> {
-        // Regular expression to extract JSON-style tool calls
-        // This pattern looks for: Tool call: {"name":"tool_name","parameters":{...}}
-        let re = Regex::new(r#"Tool call: (\{.*?\})"#).ok()?;
+
+    async fn process_chat_input(&mut self, input: &str) -> Result<()> {
+        // Add user message to conversation state
+        self.conversation_state.add_user_message(input);
         
-        let mut tool_calls = Vec::new();
-        let mut last_end = 0;
-        let mut text_parts = Vec::new();
+        // Get response from the model
+        let response = self.get_model_response().await?;
         
-        for captures in re.captures_iter(response) {
-            if let Some(json_match) = captures.get(1) {
-                // Add the text before this tool call to text_parts
-                if let Some(match_start) = captures.get(0) {
-                    let start_pos = match_start.start();
-                    if start_pos > last_end {
-                        text_parts.push(&response[last_end..start_pos]);
-                    }
-                    last_end = match_start.end();
-                }
-                
-                // Try to parse the JSON
-                let json_str = json_match.as_str();
-                if let Ok(_) = serde_json::from_str::<Value>(json_str) {
-                    // If it's valid JSON, add it to tool_calls
-                    tool_calls.push(json_str.to_string());
+        // Display response
+        self.display_response(response).await?;
+        
+        Ok(())
+    }
+
+    /// Handle the model's response, executing tool calls and feeding their
+    /// results back until the model answers with text only or `max_steps`
+    /// rounds of tool calls have been made.
+    ///
+    /// Text has already been written to the output while streaming, so only
+    /// the tool calls and the conversation state are handled here.
+    async fn display_response(&mut self, response: ModelResponse) -> Result<()> {
+        let mut response = response;
+        let mut steps = 0;
+
+        while !response.function_calls.is_empty() {
+            if steps >= self.config.max_steps() {
+                writeln!(
+                    self.output,
+                    "Stopped after {} tool steps without a final answer. Ask me to continue, or raise the limit with --max-steps.",
+                    self.config.max_steps()
+                )?;
+                if !response.text.trim().is_empty() {
+                    self.conversation_state.add_model_message(&response.text);
                 }
+                return Ok(());
             }
+
+            self.run_tool_calls(&response).await?;
+            steps += 1;
+
+            // Get follow-up response from the model
+            response = self.get_model_response().await?;
         }
-        
-        // Add any remaining text after the last tool call
-        if last_end < response.len() {
-            text_parts.push(&response[last_end..]);
+
+        self.conversation_state.add_model_message(&response.text);
+
+        Ok(())
+    }
+
+    /// Execute every function call in `response` and record both the calls
+    /// and their results in the conversation state.
+    async fn run_tool_calls(&mut self, response: &ModelResponse) -> Result<()> {
+        let mut results = Vec::new();
+
+        for tool_call in &response.function_calls {
+            let result = self.invoke_tool(tool_call).await?;
+            results.push(FunctionResponse {
+                id: tool_call.id.clone(),
+                name: tool_call.name.clone(),
+                response: result,
+            });
         }
-        
-        if !tool_calls.is_empty() {
-            // Join all text parts that aren't tool calls
-            let text_part = text_parts.join("").trim().to_string();
-            return Some((text_part, tool_calls));
+
+        self.conversation_state.add_function_calls(&response.text, &response.function_calls);
+        self.conversation_state.add_function_responses(results);
+
+        Ok(())
+    }
+
+    /// Parse, validate, approve and execute a single tool call, returning
+    /// the JSON response for the model. Tool failures are reported to the
+    /// model rather than aborting the turn.
+    async fn invoke_tool(&mut self, tool_call: &FunctionCall) -> Result<Value> {
+        let tool = match self.tool_registry.parse(tool_call, &self.tool_context.shell.current_dir()).and_then(|tool| tool.validate().map(|_| tool)) {
+            Ok(tool) => tool,
+            Err(e) => return self.tool_error(e),
+        };
+
+        if !self.approve_tool_call(&tool_call.name, tool.as_ref())? {
+            writeln!(self.output, "Skipped {}.", tool_call.name)?;
+            return Ok(json!({ "error": "The user denied permission to run this tool call." }));
         }
+
+        let mut live = LiveOutput::new(io::stdout().is_terminal());
+        let result = with_command_events(
+            &mut self.output,
+            &mut self.command_events,
+            &mut live,
+            tool.execute(&self.tool_context),
+        )
+        .await;
+        live.summary(&mut self.output)?;
         
-        None
+        match result {
+            Ok(output) => Ok(output.into_response()),
+            Err(e) => self.tool_error(e),
+        }
     }
 
-    async fn execute_tool_call(&self, tool_call: &str) -> Result<String> {
-        let tool_call: Value = serde_json::from_str(tool_call)?;
-        
-        let tool_name = tool_call["name"].as_str().unwrap_or("");
-        let parameters = tool_call["parameters"].as_object().unwrap_or(&serde_json::Map::new()).clone();
-        
-        match tool_name {
-            "execute_bash" => {
-                let command = parameters.get("command").and_then(|v| v.as_str()).unwrap_or("");
-                execute_bash::execute_bash(command).await
+    fn tool_error(&mut self, e: eyre::Report) -> Result<Value> {
+        let error_msg = format!("Error executing tool call: {}", e);
+        writeln!(self.output, "{}", error_msg)?;
+        Ok(json!({ "error": error_msg }))
+    }
+
+    /// Ask the user whether a tool call may run. Read-only tools, tools
+    /// approved with "always" and all tools under `--yes` are approved
+    /// without asking, unless the tool demands explicit approval.
+    fn approve_tool_call(&mut self, tool_name: &str, tool: &dyn Tool) -> Result<bool> {
+        if !tool.requires_approval() {
+            return Ok(true);
+        }
+
+        let explicit = tool.requires_explicit_approval();
+        if !explicit && (self.config.accept_all() || self.trusted_tools.contains(tool_name)) {
+            return Ok(true);
+        }
+
+        writeln!(self.output, "\n{}", tool.describe())?;
+
+        loop {
+            if explicit {
+                write!(self.output, "Allow this action? [y]es / [n]o: ")?;
+            } else {
+                write!(self.output, "Allow this action? [y]es / [n]o / [a]lways allow {}: ", tool_name)?;
             }
-            "fs_read" => {
-                let path = parameters.get("path").and_then(|v| v.as_str()).unwrap_or("");
-                let mode = parameters.get("mode").and_then(|v| v.as_str()).unwrap_or("Line");
-                
-                // Check if the path exists, if not, try to find similar files
-                let result = match mode {
-                    "Line" => {
-                        let start_line = parameters.get("start_line").and_then(|v| v.as_i64()).unwrap_or(1) as i32;
-                        let end_line = parameters.get("end_line").and_then(|v| v.as_i64()).unwrap_or(-1) as i32;
-                        fs_read::read_file_lines(path, start_line, end_line).await
-                    }
-                    "Directory" => {
-                        // For directory mode, create the directory if it doesn't exist
-                        let dir_path = std::path::Path::new(path);
-                        if !dir_path.exists() {
-                            // Try to create the directory
-                            match std::fs::create_dir_all(dir_path) {
-                                Ok(_) => {
-                                    tracing::info!("Created directory: {}", path);
-                                    // Return empty directory listing
-                                    return Ok(format!("Directory created: {}\nThe directory is empty.", path));
-                                }
-                                Err(e) => {
-                                    tracing::error!("Failed to create directory {}: {}", path, e);
-                                    // Continue with normal flow, the list_directory will return an error
-                                }
-                            }
-                        }
-                        fs_read::list_directory(path).await
-                    }
-                    "Search" => {
-                        let pattern = parameters.get("pattern").and_then(|v| v.as_str()).unwrap_or("");
-                        let context_lines = parameters.get("context_lines").and_then(|v| v.as_i64()).map(|v| v as usize);
-                        fs_read::search_file(path, pattern, context_lines).await
-                    }
-                    _ => bail!("Invalid fs_read mode: {}", mode)
-                };
-                
-                // If there's an error and it's about a file not found, try to list the directory
-                // to help the model understand what files are available
-                if let Err(e) = &result {
-                    if e.to_string().contains("File not found") || e.to_string().contains("not found") {
-                        // Try to list the current directory to help the model
-                        let dir_path = std::path::Path::new(path).parent().unwrap_or(std::path::Path::new("."));
-                        if let Ok(dir_listing) = fs_read::list_directory(dir_path.to_str().unwrap_or(".")).await {
-                            return Ok(format!("Error: {}.\n\nAvailable files in directory:\n{}", e, dir_listing));
-                        }
-                    }
+            self.output.flush()?;
+
+            let Some(answer) = self.input_source.read_answer()? else {
+                // No way to ask, so err on the side of not running anything
+                writeln!(self.output)?;
+                if !self.interactive {
+                    writeln!(self.output, "No answer to read, so the action was denied. Use --yes to allow it.")?;
                 }
-                
-                result
-            }
-            "fs_write" => {
-                let path = parameters.get("path").and_then(|v| v.as_str()).unwrap_or("");
-                let command = parameters.get("command").and_then(|v| v.as_str()).unwrap_or("");
-                
-                match command {
-                    "create" => {
-                        let file_text = parameters.get("file_text").and_then(|v| v.as_str()).unwrap_or("");
-                        fs_write::create_file(path, file_text).await
-                    }
-                    "str_replace" => {
-                        let old_str = parameters.get("old_str").and_then(|v| v.as_str()).unwrap_or("");
-                        let new_str = parameters.get("new_str").and_then(|v| v.as_str()).unwrap_or("");
-                        fs_write::replace_in_file(path, old_str, new_str).await
-                    }
-                    "append" => {
-                        let content = parameters.get("new_str").and_then(|v| v.as_str()).unwrap_or("");
-                        fs_write::append_to_file(path, content).await
-                    }
-                    "insert" => {
-                        let insert_line = parameters.get("insert_line").and_then(|v| v.as_i64()).unwrap_or(0) as usize;
-                        let content = parameters.get("new_str").and_then(|v| v.as_str()).unwrap_or("");
-                        fs_write::insert_in_file(path, insert_line, content).await
-                    }
-                    _ => bail!("Invalid fs_write command: {}", command)
+                return Ok(false);
+            };
+
+            match answer.trim().to_lowercase().as_str() {
+                "y" | "yes" => return Ok(true),
+                "n" | "no" => return Ok(false),
+                "a" | "always" if !explicit => {
+                    self.trusted_tools.insert(tool_name.to_string());
+                    return Ok(true);
                 }
+                _ => continue,
             }
-            _ => bail!("Unknown tool: {}", tool_name)
         }
     }
 
@@ -451,16 +610,23 @@ You don't have direct access to the user's system. Instead, you must use tools t
 When you need information about files, directories, or need to run commands, use the appropriate tool.
 
 Available tools:
-1. execute_bash - Run shell commands to gather information or perform actions
-2. fs_read - Read files or list directories
-3. fs_write - Create or modify files
+"#.to_string();
+
+        // Only the tools that are enabled, as registered
+        for (i, tool) in self.tool_registry.definitions().iter().enumerate() {
+            prompt.push_str(&format!("{}. {} - {}\n", i + 1, tool.name, first_sentence(&tool.description)));
+        }
 
+        prompt.push_str(
+            r#"
+Tools that change something may need the user's approval before they run.
 When you need to use a tool, the system will handle the formatting for you. Just focus on providing
 the correct tool name and parameters.
 
 Always use these tools when you need system information rather than asking the user to provide it.
 After receiving tool results, provide a comprehensive response based on the information gathered.
-"#.to_string();
+"#,
+        );
 
         // Add system context if available
         if let Some(context_manager) = &self.context_manager {
@@ -468,117 +634,385 @@ After receiving tool results, provide a comprehensive response based on the info
             prompt.push_str(&context_manager.get_system_context());
         }
 
+        // Add the user's own instructions from the config file
+        if let Some(additions) = &self.config.prompt.additions {
+            prompt.push_str("\n\n# Additional Instructions\n");
+            prompt.push_str(additions);
+        }
+
         prompt
     }
 
-    fn get_tool_definitions(&self) -> Vec<ToolDefinition> {
-        vec![
-            ToolDefinition {
-                name: "execute_bash".to_string(),
-                description: "Execute a bash command".to_string(),
-                parameters: json!({
-                    "type": "object",
-                    "properties": {
-                        "command": {
-                            "type": "string",
-                            "description": "The bash command to execute"
-                        }
-                    },
-                    "required": ["command"]
-                }),
-            },
-            ToolDefinition {
-                name: "fs_read".to_string(),
-                description: "Read a file or directory".to_string(),
-                parameters: json!({
-                    "type": "object",
-                    "properties": {
-                        "path": {
-                            "type": "string",
-                            "description": "Path to the file or directory"
-                        },
-                        "mode": {
-                            "type": "string",
-                            "enum": ["Line", "Directory", "Search"],
-                            "description": "Mode to read the file or directory"
-                        },
-                        "start_line": {
-                            "type": "integer",
-                            "description": "Starting line number (optional, for Line mode)"
-                        },
-                        "end_line": {
-                            "type": "integer",
-                            "description": "Ending line number (optional, for Line mode)"
-                        },
-                        "pattern": {
-                            "type": "string",
-                            "description": "Pattern to search for (required, for Search mode)"
-                        }
-                    },
-                    "required": ["path", "mode"]
-                }),
-            },
-            ToolDefinition {
-                name: "fs_write".to_string(),
-                description: "Write to a file".to_string(),
-                parameters: json!({
-                    "type": "object",
-                    "properties": {
-                        "path": {
-                            "type": "string",
-                            "description": "Path to the file"
-                        },
-                        "command": {
-                            "type": "string",
-                            "enum": ["create", "str_replace", "insert", "append"],
-                            "description": "Command to perform on the file"
-                        },
-                        "file_text": {
-                            "type": "string",
-                            "description": "Content to write to the file (for create command)"
-                        },
-                        "old_str": {
-                            "type": "string",
-                            "description": "String to replace (for str_replace command)"
-                        },
-                        "new_str": {
-                            "type": "string",
-                            "description": "New string (for str_replace, insert, append commands)"
-                        },
-                        "insert_line": {
-                            "type": "integer",
-                            "description": "Line number to insert at (for insert command)"
-                        }
-                    },
-                    "required": ["path", "command"]
-                }),
-            }
-        ]
+    fn context_budget(&self) -> usize {
+        match (self.config.context_budget, &self.provider) {
+            (Some(budget), _) => budget,
+            (None, Some(provider)) => provider.context_window() * 3 / 4,
+            (None, None) => usize::MAX,
+        }
+    }
+
+    /// Tools declared to the model, if it supports them
+    fn tool_definitions(&self) -> Vec<ToolDefinition> {
+        match &self.provider {
+            Some(provider) if provider.supports_tools() => self.tool_registry.definitions(),
+            _ => Vec::new(),
+        }
+    }
+
+    fn estimated_request_tokens(&self) -> usize {
+        estimate_tokens(&self.create_system_prompt())
+            + estimate_tool_tokens(&self.tool_definitions())
+            + self.conversation_state.estimated_tokens()
+    }
+
+    /// Compact the history if the next request would exceed the context budget
+    async fn ensure_within_budget(&mut self) -> Result<()> {
+        let budget = self.context_budget();
+        let estimate = self.estimated_request_tokens();
+        if estimate <= budget {
+            return Ok(());
+        }
+
+        // The estimate is rough, so confirm with the provider before
+        // compacting. Token counts don't include the tool declarations.
+        let tokens = match &self.provider {
+            Some(provider) => provider
+                .count_tokens(&self.create_system_prompt(), self.conversation_state.get_messages())
+                .await
+                .map(|tokens| tokens + estimate_tool_tokens(&self.tool_definitions()))
+                .unwrap_or(estimate),
+            None => estimate,
+        };
+        if tokens <= budget {
+            return Ok(());
+        }
+
+        writeln!(
+            self.output,
+            "The conversation is using about {} of {} tokens; compacting older messages...",
+            tokens, budget
+        )?;
+        self.compact(false).await
     }
 
-    async fn get_gemini_response(&self) -> Result<String> {
-        let client = match &self.gemini_client {
-            Some(client) => client,
-            None => bail!("Gemini client not initialized"),
+    /// Free up context by truncating tool outputs in older turns and, if that
+    /// is not enough or `force` is set, replacing older turns with a summary
+    /// written by the model. The most recent turns are kept verbatim unless
+    /// they alone exceed the budget, as when one prompt leads to many large
+    /// tool outputs; then all but the latest tool outputs are truncated.
+    async fn compact(&mut self, force: bool) -> Result<()> {
+        let budget = self.context_budget();
+        let boundary = self.conversation_state.compaction_boundary(KEEP_RECENT_TURNS);
+        if boundary == 0 {
+            let latest = self.conversation_state.latest_tool_outputs();
+            if self.estimated_request_tokens() > budget && self.conversation_state.truncate_tool_outputs(latest) {
+                writeln!(self.output, "Truncated earlier tool outputs in the recent messages.")?;
+            } else {
+                writeln!(self.output, "Nothing to compact yet; only the most recent turns remain.")?;
+            }
+            return Ok(());
+        }
+
+        let truncated = self.conversation_state.truncate_tool_outputs(boundary);
+        if !force && truncated && self.estimated_request_tokens() <= budget {
+            writeln!(self.output, "Truncated tool outputs in older messages.")?;
+            return Ok(());
+        }
+
+        let provider = match &self.provider {
+            Some(provider) => provider,
+            None => bail!("Model provider not initialized"),
         };
+
+        let request = Content::text_message(
+            Role::User,
+            format!(
+                "Summarize the following conversation:\n\n{}",
+                self.conversation_state.transcript(boundary)
+            ),
+        );
+        let summary = with_retry_notices(
+            &mut self.output,
+            &mut self.retry_notices,
+            provider.generate(SUMMARY_PROMPT, &[request], &[]),
+        ).await?;
+
+        self.conversation_state.replace_with_summary(boundary, &summary.text);
+
+        // If the recent turns alone are still too large, shorten their tool outputs too
+        if self.estimated_request_tokens() > budget {
+            let len = self.conversation_state.get_messages().len();
+            self.conversation_state.truncate_tool_outputs(len);
+        }
+
+        writeln!(
+            self.output,
+            "Compacted {} earlier messages into a summary (about {} tokens now in use).",
+            boundary,
+            self.estimated_request_tokens()
+        )?;
+
+        Ok(())
+    }
+
+    /// Get the model's next response. If the model produces a malformed
+    /// function call it is told what went wrong and asked to try again, up to
+    /// `MAX_MALFORMED_CALL_RETRIES` times. Other model errors are returned.
+    async fn get_model_response(&mut self) -> Result<ModelResponse> {
+        let mut retries = 0;
+        
+        loop {
+            let error = match self.stream_model_response().await {
+                Ok(response) => return Ok(response),
+                Err(e) => e,
+            };
+            
+            let message = match error.downcast_ref::<ModelError>() {
+                Some(ModelError::MalformedFunctionCall { message }) if retries < MAX_MALFORMED_CALL_RETRIES => {
+                    message.clone()
+                }
+                _ => return Err(error),
+            };
+            retries += 1;
+            
+            writeln!(self.output, "The model produced a malformed tool call, asking it to try again...")?;
+            self.conversation_state.add_user_message(&format!(
+                "Your last function call could not be parsed{}. Call the function again with arguments \
+                 that match its declared parameters, or answer without calling a function.",
+                message.map(|m| format!(" ({})", m)).unwrap_or_default()
+            ));
+        }
+    }
+    
+    /// Stream a response from the model, writing text to the output as it
+    /// arrives, and return the complete response once the stream ends.
+    async fn stream_model_response(&mut self) -> Result<ModelResponse> {
+        self.ensure_within_budget().await?;
         
         // Create system prompt
         let system_prompt = self.create_system_prompt();
         
+        let provider = match &self.provider {
+            Some(provider) => provider,
+            None => bail!("Model provider not initialized"),
+        };
+        
+        // Define available tools
+        let tools = self.tool_definitions();
+        
         // Get conversation history
         let messages = self.conversation_state.get_messages();
         
-        // Convert messages to format expected by Gemini client
-        let formatted_messages: Vec<(&str, &str)> = messages.iter()
-            .map(|(role, content)| (role.as_str(), content.as_str()))
-            .collect();
+        // Call the model API
+        let mut stream = with_retry_notices(
+            &mut self.output,
+            &mut self.retry_notices,
+            provider.stream(&system_prompt, messages, &tools),
+        ).await?;
         
-        // Define available tools
-        let tools = self.get_tool_definitions();
+        // Render markdown on terminals and pass it through unchanged otherwise
+        let mut markdown = self.render_markdown.then(ParseState::new);
         
-        // Call Gemini API
-        let response = client.generate_content(&system_prompt, &formatted_messages, &tools).await?;
+        let mut response = ModelResponse::default();
+        while let Some(chunk) = stream.next().await {
+            let chunk = match chunk {
+                Ok(chunk) => chunk,
+                Err(e) => {
+                    if let Some(markdown) = &mut markdown {
+                        write!(self.output, "{}", markdown.finish())?;
+                    }
+                    // Keep the error off the line of any partial text
+                    if !response.text.is_empty() && !response.text.ends_with('\n') {
+                        writeln!(self.output)?;
+                    }
+                    return Err(e);
+                }
+            };
+            
+            if !chunk.text.is_empty() {
+                match &mut markdown {
+                    Some(markdown) => write!(self.output, "{}", markdown.push(&chunk.text))?,
+                    None => write!(self.output, "{}", chunk.text)?,
+                }
+                self.output.flush()?;
+                response.text.push_str(&chunk.text);
+            }
+            
+            response.function_calls.extend(chunk.function_calls);
+        }
+        
+        if let Some(markdown) = &mut markdown {
+            write!(self.output, "{}", markdown.finish())?;
+        }
+        if !response.text.is_empty() && !response.text.ends_with('\n') {
+            writeln!(self.output)?;
+        }
         
         Ok(response)
     }
 }
+
+/// The `/help` text, listing every slash command
+fn help_text() -> String {
+    let mut lines = vec![String::new(), String::from("Gemini Chat CLI"), String::new()];
+    for spec in COMMANDS {
+        lines.push(format!("{:<18}{}", spec.usage, spec.description));
+    }
+    lines.push(String::new());
+    lines.push(format!("{:<18}{}", "!{command}", "Quickly execute a command in your current session"));
+    lines.join("\n")
+}
+
+/// The first sentence of a tool description, to list the tool in the
+/// system prompt without repeating its full schema documentation
+fn first_sentence(description: &str) -> &str {
+    let line = description.lines().next().unwrap_or_default().trim();
+    match line.find(". ") {
+        Some(end) => &line[..=end],
+        None => line,
+    }
+}
+
+/// Wait for a model request, writing the provider's retry notices to the
+/// output while the request is pending
+async fn with_retry_notices<T>(
+    output: &mut Box<dyn Write>,
+    notices: &mut UnboundedReceiver<RetryNotice>,
+    request: impl Future<Output = Result<T>>,
+) -> Result<T> {
+    tokio::pin!(request);
+    
+    loop {
+        tokio::select! {
+            result = &mut request => return result,
+            Some(notice) = notices.recv() => {
+                writeln!(output, "{}", notice)?;
+                output.flush()?;
+            }
+        }
+    }
+}
+
+/// Wait for a tool or shell command, showing the output of the commands it
+/// runs while it is pending
+async fn with_command_events<T>(
+    output: &mut Box<dyn Write>,
+    events: &mut UnboundedReceiver<CommandEvent>,
+    live: &mut LiveOutput,
+    command: impl Future<Output = Result<T>>,
+) -> Result<T> {
+    tokio::pin!(command);
+    
+    let result = loop {
+        tokio::select! {
+            result = &mut command => break result,
+            Some(event) = events.recv() => {
+                live.push(event);
+                // Draw once for everything that arrived in the meantime
+                while let Ok(event) = events.try_recv() {
+                    live.push(event);
+                }
+                live.draw(output.as_mut())?;
+            }
+        }
+    };
+    
+    // Events sent just before the command finished
+    while let Ok(event) = events.try_recv() {
+        live.push(event);
+    }
+    live.draw(output.as_mut())?;
+    
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+    use crate::mock_server::{self, MockServer};
+    use crate::model_provider::{Part, ProviderKind};
+
+    /// Output that the test can read after the chat has written to it
+    #[derive(Clone, Default)]
+    struct SharedOutput(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedOutput {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl SharedOutput {
+        fn text(&self) -> String {
+            String::from_utf8_lossy(&self.0.lock().unwrap()).to_string()
+        }
+    }
+
+    #[tokio::test]
+    async fn scripted_session() {
+        let reply = format!(
+            "{}\n{}\n",
+            json!({ "message": { "role": "assistant", "content": "Hello from the model" }, "done": false }),
+            json!({ "message": { "role": "assistant", "content": "" }, "done": true }),
+        );
+        let server = MockServer::start(vec![mock_server::response(200, &[("Content-Type", "application/x-ndjson")], &reply)]).await;
+        let config = Config {
+            provider: Some(ProviderKind::Ollama),
+            base_url: Some(server.url.clone()),
+            ..Config::default()
+        };
+        let output = SharedOutput::default();
+        let input = InputSource::lines(["/help", "!echo from the shell", "Say hello", "/quit", "never read"]);
+
+        let mut chat = ChatContext::new(Box::new(output.clone()), input, false, config).unwrap();
+        chat.render_markdown = false;
+        let exit_code = chat.run().await.unwrap();
+
+        assert_eq!(exit_code, ExitCode::SUCCESS);
+        let text = output.text();
+        assert!(text.contains("/quit"), "{}", text);
+        assert!(text.contains("from the shell\n"), "{}", text);
+        assert!(text.contains("Hello from the model"), "{}", text);
+        assert_eq!(chat.conversation_state.get_messages().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn compacting_one_long_turn_keeps_the_latest_tool_output() {
+        let config = Config {
+            context_budget: Some(5_000),
+            ..Config::default()
+        };
+        let input = InputSource::lines(Vec::<String>::new());
+        let mut chat = ChatContext::new(Box::new(SharedOutput::default()), input, false, config).unwrap();
+
+        chat.conversation_state.add_user_message("Read all the logs");
+        for i in 0..3 {
+            let call = FunctionCall { id: None, name: String::from("fs_read"), args: json!({ "path": i.to_string() }) };
+            chat.conversation_state.add_function_calls("", &[call]);
+            chat.conversation_state.add_function_responses(vec![FunctionResponse {
+                id: None,
+                name: String::from("fs_read"),
+                response: json!({ "result": "x".repeat(10_000) }),
+            }]);
+        }
+
+        chat.compact(false).await.unwrap();
+
+        let outputs: Vec<usize> = chat.conversation_state.get_messages().iter()
+            .flat_map(|message| &message.parts)
+            .filter_map(|part| match part {
+                Part::FunctionResponse(response) => Some(response.response["result"].as_str().unwrap().len()),
+                _ => None,
+            })
+            .collect();
+        assert!(outputs[0] < 3_000 && outputs[1] < 3_000, "{:?}", outputs);
+        assert_eq!(outputs[2], 10_000);
+    }
+}
@@ -1,8 +1,577 @@
+use crossterm::style::{Color, Stylize};
+
+/// Width of horizontal rules, in columns
+const RULE_WIDTH: usize = 40;
+
+/// Incremental markdown renderer for model output.
+///
+/// Text is pushed in as it streams from the model and rendered a line at a
+/// time, since most markdown constructs can only be recognized once their
+/// line is complete. Tables are held back until their last row has arrived
+/// so that the columns can be aligned.
+#[derive(Debug, Default)]
 pub struct ParseState {
-    // Add fields as needed
+    /// Text after the last complete line
+    pending: String,
+
+    /// The fenced code block the renderer is inside of, if any
+    code_block: Option<CodeBlock>,
+
+    /// Rows of the table currently being received
+    table_rows: Vec<String>,
+}
+
+#[derive(Debug)]
+struct CodeBlock {
+    /// The characters that opened the block, which must also close it
+    fence: String,
+    syntax: Option<&'static Syntax>,
+}
+
+impl ParseState {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add streamed text and return the rendered output for every line it
+    /// completes
+    pub fn push(&mut self, text: &str) -> String {
+        self.pending.push_str(text);
+
+        let mut output = String::new();
+        while let Some(newline) = self.pending.find('\n') {
+            let line: String = self.pending.drain(..=newline).collect();
+            output.push_str(&self.render_line(line.trim_end_matches('\n')));
+        }
+        output
+    }
+
+    /// Render whatever is left once the response is complete. The last line
+    /// is returned without a trailing newline, as it was received.
+    pub fn finish(&mut self) -> String {
+        let line = std::mem::take(&mut self.pending);
+        if line.is_empty() {
+            return self.flush_table();
+        }
+
+        let mut output = self.render_line(&line);
+        output.push_str(&self.flush_table());
+        if output.ends_with('\n') {
+            output.pop();
+        }
+        output
+    }
+
+    fn render_line(&mut self, line: &str) -> String {
+        if let Some(block) = &self.code_block {
+            if line.trim_start().starts_with(block.fence.as_str()) {
+                self.code_block = None;
+                return format!("{}\n", line.dark_grey());
+            }
+            return format!("{}\n", highlight_code(line, block.syntax));
+        }
+
+        let trimmed = line.trim_start();
+
+        if trimmed.starts_with('|') {
+            self.table_rows.push(trimmed.to_string());
+            return String::new();
+        }
+
+        let mut output = self.flush_table();
+
+        if let Some(fence) = ["```", "~~~"].into_iter().find(|fence| trimmed.starts_with(fence)) {
+            let language = trimmed.trim_start_matches(fence).trim();
+            self.code_block = Some(CodeBlock {
+                fence: fence.to_string(),
+                syntax: Syntax::for_language(language),
+            });
+            output.push_str(&format!("{}\n", line.dark_grey()));
+            return output;
+        }
+
+        output.push_str(&render_block(line));
+        output.push('\n');
+        output
+    }
+
+    fn flush_table(&mut self) -> String {
+        if self.table_rows.is_empty() {
+            return String::new();
+        }
+        let rows = std::mem::take(&mut self.table_rows);
+        render_table(&rows)
+    }
+}
+
+/// Render a line outside of code blocks and tables
+fn render_block(line: &str) -> String {
+    let trimmed = line.trim_start();
+    let indent = &line[..line.len() - trimmed.len()];
+
+    // Headings
+    let level = trimmed.chars().take_while(|&c| c == '#').count();
+    if (1..=6).contains(&level) && trimmed[level..].starts_with(' ') {
+        let title = render_inline(trimmed[level..].trim(), false);
+        return match level {
+            1 => title.bold().underlined().with(Color::Cyan).to_string(),
+            _ => title.bold().with(Color::Cyan).to_string(),
+        };
+    }
+
+    // Block quotes, which may contain any other block
+    if let Some(quoted) = trimmed.strip_prefix('>') {
+        let quoted = quoted.strip_prefix(' ').unwrap_or(quoted);
+        return format!("{}{} {}", indent, "│".dark_grey(), render_block(quoted).italic());
+    }
+
+    // Horizontal rules
+    let compact: String = trimmed.chars().filter(|c| !c.is_whitespace()).collect();
+    if compact.len() >= 3 && ['-', '*', '_'].iter().any(|&marker| compact.chars().all(|c| c == marker)) {
+        return "─".repeat(RULE_WIDTH).dark_grey().to_string();
+    }
+
+    // Unordered lists
+    for marker in ["- ", "* ", "+ "] {
+        if let Some(item) = trimmed.strip_prefix(marker) {
+            return format!("{}{} {}", indent, "•".with(Color::Cyan), render_inline(item, true));
+        }
+    }
+
+    // Ordered lists
+    let digits = trimmed.chars().take_while(|c| c.is_ascii_digit()).count();
+    if digits > 0 {
+        let rest = &trimmed[digits..];
+        if let Some(item) = rest.strip_prefix(". ").or_else(|| rest.strip_prefix(") ")) {
+            let number = &trimmed[..digits + 1];
+            return format!("{}{} {}", indent, number.with(Color::Cyan), render_inline(item, true));
+        }
+    }
+
+    format!("{}{}", indent, render_inline(trimmed, true))
+}
+
+/// Render emphasis, strikethrough, inline code and links. Without `styled`
+/// the markup is removed, which gives the displayed width of the text.
+fn render_inline(text: &str, styled: bool) -> String {
+    let mut output = String::new();
+    let mut rest = text;
+    let mut previous: Option<char> = None;
+
+    while let Some(c) = rest.chars().next() {
+        if c == '\\' {
+            if let Some(escaped) = rest[1..].chars().next().filter(|c| c.is_ascii_punctuation()) {
+                output.push(escaped);
+                previous = Some(escaped);
+                rest = &rest[1 + escaped.len_utf8()..];
+                continue;
+            }
+        }
+
+        if let Some((span, len)) = parse_span(rest, previous, styled) {
+            output.push_str(&span);
+            previous = rest[..len].chars().last();
+            rest = &rest[len..];
+            continue;
+        }
+
+        output.push(c);
+        previous = Some(c);
+        rest = &rest[c.len_utf8()..];
+    }
+
+    output
+}
+
+/// Applies terminal styling to a rendered span
+type StyleFn = fn(String) -> String;
+
+/// Try to parse an inline span at the start of `text`, returning the
+/// rendered span and the number of bytes it consumed
+fn parse_span(text: &str, previous: Option<char>, styled: bool) -> Option<(String, usize)> {
+    let style = |content: &str, apply: StyleFn| {
+        if styled {
+            apply(content.to_string())
+        } else {
+            content.to_string()
+        }
+    };
+
+    if let Some(after) = text.strip_prefix('`') {
+        let end = after.find('`')?;
+        let code = style(&after[..end], |s| s.with(Color::Yellow).to_string());
+        return Some((code, end + 2));
+    }
+
+    if let Some(after) = text.strip_prefix('[') {
+        let close = after.find(']')?;
+        if !after[close..].starts_with("](") {
+            return None;
+        }
+        let url_len = after[close + 2..].find(')')?;
+        let label = &after[..close];
+        let url = &after[close + 2..close + 2 + url_len];
+        let rendered = if label == url || label.is_empty() {
+            style(url, |s| s.underlined().to_string())
+        } else {
+            let label = style(label, |s| s.underlined().to_string());
+            let url = style(&format!("({})", url), |s| s.dark_grey().to_string());
+            format!("{} {}", label, url)
+        };
+        return Some((rendered, 1 + close + 2 + url_len + 1));
+    }
+
+    // Intraword underscores, as in snake_case names, are not emphasis
+    let intraword = previous.is_some_and(|c| c.is_alphanumeric());
+
+    let delimiters: [(&str, StyleFn); 5] = [
+        ("**", |s| s.bold().to_string()),
+        ("__", |s| s.bold().to_string()),
+        ("~~", |s| s.crossed_out().to_string()),
+        ("*", |s| s.italic().to_string()),
+        ("_", |s| s.italic().to_string()),
+    ];
+
+    for (delimiter, apply) in delimiters {
+        if delimiter.starts_with('_') && intraword {
+            continue;
+        }
+        let Some(after) = text.strip_prefix(delimiter) else {
+            continue;
+        };
+        if after.starts_with(char::is_whitespace) || after.starts_with(delimiter) {
+            continue;
+        }
+        let Some(end) = find_closing(after, delimiter) else {
+            continue;
+        };
+        let content = render_inline(&after[..end], false);
+        return Some((style(&content, apply), end + 2 * delimiter.len()));
+    }
+
+    None
+}
+
+/// Find a closing delimiter that follows non-whitespace and, for
+/// underscores, isn't followed by a word character
+fn find_closing(text: &str, delimiter: &str) -> Option<usize> {
+    let mut start = 0;
+    while let Some(offset) = text[start..].find(delimiter) {
+        let index = start + offset;
+        let before = text[..index].chars().last();
+        let after = text[index + delimiter.len()..].chars().next();
+        let closes = before.is_some_and(|c| !c.is_whitespace())
+            && !(delimiter.starts_with('_') && after.is_some_and(|c| c.is_alphanumeric()));
+        if closes && index > 0 {
+            return Some(index);
+        }
+        start = index + delimiter.len();
+    }
+    None
+}
+
+/// Align the columns of a table with box drawing separators. The header row
+/// is printed in bold and the delimiter row becomes a horizontal line.
+fn render_table(rows: &[String]) -> String {
+    let rows: Vec<Vec<String>> = rows.iter().map(|row| split_row(row)).collect();
+
+    let is_delimiter = |row: &Vec<String>| {
+        row.iter().all(|cell| !cell.is_empty() && cell.chars().all(|c| matches!(c, '-' | ':')))
+    };
+
+    let columns = rows.iter().map(Vec::len).max().unwrap_or(0);
+    let mut widths = vec![0; columns];
+    for row in rows.iter().filter(|row| !is_delimiter(row)) {
+        for (i, cell) in row.iter().enumerate() {
+            widths[i] = widths[i].max(render_inline(cell, false).chars().count());
+        }
+    }
+
+    let mut output = String::new();
+    for (index, row) in rows.iter().enumerate() {
+        if is_delimiter(row) {
+            let line = widths.iter().map(|&w| "─".repeat(w + 2)).collect::<Vec<_>>().join("┼");
+            output.push_str(&line.dark_grey().to_string());
+            output.push('\n');
+            continue;
+        }
+
+        let header = index == 0 && rows.get(1).is_some_and(is_delimiter);
+        let cells = (0..columns)
+            .map(|i| {
+                let cell = row.get(i).map(String::as_str).unwrap_or("");
+                let padding = widths[i] - render_inline(cell, false).chars().count();
+                let rendered = if header {
+                    render_inline(cell, false).bold().to_string()
+                } else {
+                    render_inline(cell, true)
+                };
+                format!(" {}{} ", rendered, " ".repeat(padding))
+            })
+            .collect::<Vec<_>>();
+        output.push_str(&cells.join(&"│".dark_grey().to_string()));
+        output.push('\n');
+    }
+    output
+}
+
+/// Split a table row into trimmed cells, honoring escaped pipes
+fn split_row(row: &str) -> Vec<String> {
+    let row = row.trim();
+    let row = row.strip_prefix('|').unwrap_or(row);
+    let row = row.strip_suffix('|').unwrap_or(row);
+
+    let mut cells = vec![String::new()];
+    let mut chars = row.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' if chars.peek() == Some(&'|') => {
+                cells.last_mut().unwrap().push('|');
+                chars.next();
+            }
+            '|' => cells.push(String::new()),
+            _ => cells.last_mut().unwrap().push(c),
+        }
+    }
+    cells.into_iter().map(|cell| cell.trim().to_string()).collect()
+}
+
+/// Just enough knowledge of a language to color its keywords, strings and
+/// comments
+#[derive(Debug)]
+struct Syntax {
+    keywords: &'static [&'static str],
+    line_comment: Option<&'static str>,
+    /// Whether single quotes delimit strings, rather than e.g. Rust lifetimes
+    single_quote_strings: bool,
+}
+
+const RUST: Syntax = Syntax {
+    keywords: &[
+        "as", "async", "await", "break", "const", "continue", "crate", "dyn", "else", "enum", "extern", "false",
+        "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut", "pub", "ref", "return",
+        "self", "Self", "static", "struct", "super", "trait", "true", "type", "unsafe", "use", "where", "while",
+    ],
+    line_comment: Some("//"),
+    single_quote_strings: false,
+};
+
+const PYTHON: Syntax = Syntax {
+    keywords: &[
+        "and", "as", "assert", "async", "await", "break", "class", "continue", "def", "del", "elif", "else",
+        "except", "False", "finally", "for", "from", "global", "if", "import", "in", "is", "lambda", "None",
+        "nonlocal", "not", "or", "pass", "raise", "return", "True", "try", "while", "with", "yield",
+    ],
+    line_comment: Some("#"),
+    single_quote_strings: true,
+};
+
+const JAVASCRIPT: Syntax = Syntax {
+    keywords: &[
+        "async", "await", "break", "case", "catch", "class", "const", "continue", "default", "delete", "do",
+        "else", "export", "extends", "false", "finally", "for", "from", "function", "if", "import", "in",
+        "instanceof", "interface", "let", "new", "null", "return", "super", "switch", "this", "throw", "true",
+        "try", "type", "typeof", "undefined", "var", "void", "while", "yield",
+    ],
+    line_comment: Some("//"),
+    single_quote_strings: true,
+};
+
+const GO: Syntax = Syntax {
+    keywords: &[
+        "break", "case", "chan", "const", "continue", "default", "defer", "else", "fallthrough", "false", "for",
+        "func", "go", "goto", "if", "import", "interface", "map", "nil", "package", "range", "return", "select",
+        "struct", "switch", "true", "type", "var",
+    ],
+    line_comment: Some("//"),
+    single_quote_strings: true,
+};
+
+const C_LIKE: Syntax = Syntax {
+    keywords: &[
+        "auto", "bool", "break", "case", "catch", "char", "class", "const", "continue", "default", "do", "double",
+        "else", "enum", "extends", "extern", "false", "final", "float", "for", "if", "implements", "import", "int",
+        "long", "namespace", "new", "null", "nullptr", "package", "private", "protected", "public", "return",
+        "short", "signed", "sizeof", "static", "struct", "switch", "this", "throw", "true", "try", "typedef",
+        "union", "unsigned", "using", "void", "volatile", "while",
+    ],
+    line_comment: Some("//"),
+    single_quote_strings: true,
+};
+
+const SHELL: Syntax = Syntax {
+    keywords: &[
+        "case", "do", "done", "elif", "else", "esac", "export", "fi", "for", "function", "if", "in", "local",
+        "return", "then", "until", "while",
+    ],
+    line_comment: Some("#"),
+    single_quote_strings: true,
+};
+
+/// Configuration formats: strings and comments only
+const CONFIG: Syntax = Syntax {
+    keywords: &["true", "false", "null"],
+    line_comment: Some("#"),
+    single_quote_strings: true,
+};
+
+const JSON: Syntax = Syntax {
+    keywords: &["true", "false", "null"],
+    line_comment: None,
+    single_quote_strings: false,
+};
+
+impl Syntax {
+    fn for_language(language: &str) -> Option<&'static Syntax> {
+        let language = language.split_whitespace().next().unwrap_or("").to_lowercase();
+        Some(match language.as_str() {
+            "rust" | "rs" => &RUST,
+            "python" | "py" => &PYTHON,
+            "javascript" | "js" | "jsx" | "typescript" | "ts" | "tsx" => &JAVASCRIPT,
+            "go" | "golang" => &GO,
+            "c" | "h" | "cpp" | "c++" | "cc" | "java" | "kotlin" | "cs" | "csharp" => &C_LIKE,
+            "sh" | "bash" | "zsh" | "shell" | "console" => &SHELL,
+            "toml" | "yaml" | "yml" | "ini" | "dockerfile" | "makefile" => &CONFIG,
+            "json" => &JSON,
+            _ => return None,
+        })
+    }
 }
 
-pub fn interpret_markdown(text: &str) -> String {
-    // Simple implementation for now
-    text.to_string()
+/// Color a single line of code. Strings and comments are only recognized
+/// within the line, which is good enough for terminal output.
+fn highlight_code(line: &str, syntax: Option<&Syntax>) -> String {
+    let Some(syntax) = syntax else {
+        return line.to_string();
+    };
+
+    let mut output = String::new();
+    let mut rest = line;
+
+    while let Some(c) = rest.chars().next() {
+        if syntax.line_comment.is_some_and(|comment| rest.starts_with(comment)) {
+            output.push_str(&rest.dark_grey().to_string());
+            break;
+        }
+
+        if c == '"' || c == '`' || (c == '\'' && syntax.single_quote_strings) {
+            let len = string_length(rest, c);
+            output.push_str(&rest[..len].with(Color::Green).to_string());
+            rest = &rest[len..];
+            continue;
+        }
+
+        if c.is_alphanumeric() || c == '_' {
+            let len = rest.find(|c: char| !(c.is_alphanumeric() || c == '_')).unwrap_or(rest.len());
+            let word = &rest[..len];
+            if c.is_ascii_digit() {
+                output.push_str(&word.with(Color::Yellow).to_string());
+            } else if syntax.keywords.contains(&word) {
+                output.push_str(&word.with(Color::Magenta).to_string());
+            } else {
+                output.push_str(word);
+            }
+            rest = &rest[len..];
+            continue;
+        }
+
+        output.push(c);
+        rest = &rest[c.len_utf8()..];
+    }
+
+    output
+}
+
+/// Length in bytes of the string literal at the start of `text`, up to and
+/// including the closing quote or the end of the line
+fn string_length(text: &str, quote: char) -> usize {
+    let mut escaped = false;
+    for (index, c) in text.char_indices().skip(1) {
+        if escaped {
+            escaped = false;
+        } else if c == '\\' {
+            escaped = true;
+        } else if c == quote {
+            return index + c.len_utf8();
+        }
+    }
+    text.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Render a response that arrives in the given chunks
+    fn render(chunks: &[&str]) -> String {
+        let mut state = ParseState::new();
+        let mut output: String = chunks.iter().map(|chunk| state.push(chunk)).collect();
+        output.push_str(&state.finish());
+        output
+    }
+
+    /// The text of rendered output, without its styling
+    fn plain(text: &str) -> String {
+        let mut result = String::new();
+        let mut chars = text.chars();
+        while let Some(c) = chars.next() {
+            if c == '\x1b' {
+                chars.by_ref().find(|c| c.is_ascii_alphabetic());
+            } else {
+                result.push(c);
+            }
+        }
+        result
+    }
+
+    #[test]
+    fn renders_lines_as_they_complete() {
+        let mut state = ParseState::new();
+        assert_eq!(state.push("Some **bo"), "");
+
+        let line = state.push("ld** text\nmore");
+        assert_eq!(plain(&line), "Some bold text\n");
+        assert!(line.contains(&"bold".bold().to_string()));
+
+        assert_eq!(plain(&state.finish()), "more");
+    }
+
+    #[test]
+    fn renders_the_same_however_the_text_is_split() {
+        let text = "# Title\nA *b* `c` [d](https://e)\n";
+        assert_eq!(render(&[text]), render(&["# Ti", "tle\nA *", "b* `c` [d](ht", "tps://e)\n"]));
+        assert_eq!(plain(&render(&[text])), "Title\nA b c d (https://e)\n");
+    }
+
+    #[test]
+    fn renders_lists_quotes_and_rules() {
+        assert_eq!(
+            plain(&render(&["- one\n  * nested\n2. two\n> quoted\n---\n"])),
+            format!("• one\n  • nested\n2. two\n│ quoted\n{}\n", "─".repeat(RULE_WIDTH))
+        );
+    }
+
+    #[test]
+    fn keeps_underscores_inside_words() {
+        assert_eq!(plain(&render(&["call my_func_name or _this_\n"])), "call my_func_name or this\n");
+        assert_eq!(plain(&render(&["2 * 3 * 4 and \\*literal\\*\n"])), "2 * 3 * 4 and *literal*\n");
+    }
+
+    #[test]
+    fn leaves_code_blocks_unformatted() {
+        let text = "```rust\nlet x = \"**not bold**\";\n```\nafter\n";
+        assert_eq!(plain(&render(&[text])), text);
+    }
+
+    #[test]
+    fn aligns_tables_once_complete() {
+        let mut state = ParseState::new();
+        assert_eq!(state.push("| a | bb |\n|---|---|\n| ccc | d |\n"), "");
+
+        let table = state.push("done\n");
+        assert_eq!(
+            plain(&table),
+            format!(" a   │ bb \n{}┼{}\n ccc │ d  \ndone\n", "─".repeat(5), "─".repeat(4))
+        );
+    }
 }
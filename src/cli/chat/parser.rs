@@ -1 +1,190 @@
-// Placeholder for parser implementation
+use winnow::ascii::space0;
+use winnow::combinator::{eof, opt, preceded, rest, terminated};
+use winnow::token::{take_till1, take_while};
+use winnow::{IResult, Parser};
+
+use super::command::{Arity, Command, CommandSpec, COMMANDS};
+
+/// Why a line of input couldn't be turned into a `Command`
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum CommandError {
+    #[error("Unknown command /{name}.{}", suggestion_text(.suggestion))]
+    Unknown {
+        name: String,
+        suggestion: Option<&'static str>,
+    },
+
+    #[error("Usage: {0}")]
+    Usage(&'static str),
+}
+
+fn suggestion_text(suggestion: &Option<&'static str>) -> String {
+    match suggestion {
+        Some(name) => format!(" Did you mean /{}?", name),
+        None => String::from(" Type /help to see the available commands."),
+    }
+}
+
+/// Parse a line of user input.
+///
+/// Lines starting with `!` are shell commands and lines starting with a
+/// slash command name followed by whitespace or the end of the line are
+/// slash commands. Everything else, including text that merely starts with
+/// a path such as `/etc/hosts`, is a chat message.
+pub fn parse_command(input: &str) -> Result<Command, CommandError> {
+    let trimmed = input.trim();
+
+    if let Ok((_, command)) = shell_command(trimmed) {
+        if command.is_empty() {
+            return Err(CommandError::Usage("!<command>"));
+        }
+        return Ok(Command::ShellCommand(command.to_string()));
+    }
+
+    let Ok((_, (name, arguments))) = slash_command(trimmed) else {
+        return Ok(Command::ChatMessage(input.to_string()));
+    };
+
+    let Some(spec) = CommandSpec::find(name) else {
+        return Err(CommandError::Unknown {
+            name: name.to_string(),
+            suggestion: suggest(name),
+        });
+    };
+
+    let argument = match (spec.arity, arguments.as_slice()) {
+        (Arity::None, []) | (Arity::Optional, []) => None,
+        (Arity::Optional | Arity::Required, [argument]) => Some(argument.to_string()),
+        _ => return Err(CommandError::Usage(spec.usage)),
+    };
+
+    Ok(match spec.name {
+        "help" => Command::Help {
+            command: argument.map(|name| name.trim_start_matches('/').to_string()),
+        },
+        "clear" => Command::Clear,
+        "quit" => Command::Quit,
+        "save" => Command::Save { name: argument },
+        "load" => Command::Load {
+            name: argument.unwrap_or_default(),
+        },
+        "sessions" => Command::Sessions,
+        "compact" => Command::Compact,
+        "config" => Command::Config,
+        "model" => Command::Model { name: argument },
+        "editor" => Command::Editor,
+        "shell" => match argument.as_deref() {
+            Some("reset") => Command::ShellReset,
+            _ => return Err(CommandError::Usage(spec.usage)),
+        },
+        _ => unreachable!("command /{} has no parser", spec.name),
+    })
+}
+
+/// `!` followed by the command line
+fn shell_command(input: &str) -> IResult<&str, &str> {
+    preceded('!', rest).map(str::trim).parse_next(input)
+}
+
+/// `/name` followed by whitespace separated arguments
+fn slash_command(input: &str) -> IResult<&str, (&str, Vec<&str>)> {
+    let (input, name) = preceded('/', take_while(1.., is_name_char)).parse_next(input)?;
+    let (input, arguments) = terminated(arguments, eof).parse_next(input)?;
+    Ok((input, (name, arguments)))
+}
+
+/// Whitespace separated words. The name of a command must be followed by
+/// whitespace, so `/etc/hosts` is not an `/etc` command.
+fn arguments(input: &str) -> IResult<&str, Vec<&str>> {
+    let mut argument = opt(preceded(
+        take_while(1.., char::is_whitespace),
+        take_till1(char::is_whitespace),
+    ));
+
+    let mut arguments = Vec::new();
+    let mut input = input;
+    while let (rest, Some(word)) = argument.parse_next(input)? {
+        arguments.push(word);
+        input = rest;
+    }
+    let (input, _) = space0.parse_next(input)?;
+    Ok((input, arguments))
+}
+
+fn is_name_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '-' || c == '_'
+}
+
+/// The only command starting with `name`, to suggest for a mistyped command
+fn suggest(name: &str) -> Option<&'static str> {
+    let mut matches = COMMANDS.iter().filter(|spec| spec.name.starts_with(name));
+    match (matches.next(), matches.next()) {
+        (Some(spec), None) => Some(spec.name),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_slash_commands() {
+        assert_eq!(parse_command("/quit"), Ok(Command::Quit));
+        assert_eq!(parse_command("  /clear  "), Ok(Command::Clear));
+        assert_eq!(parse_command("/help"), Ok(Command::Help { command: None }));
+        assert_eq!(
+            parse_command("/help /save"),
+            Ok(Command::Help {
+                command: Some(String::from("save"))
+            })
+        );
+        assert_eq!(
+            parse_command("/load monday"),
+            Ok(Command::Load {
+                name: String::from("monday")
+            })
+        );
+        assert_eq!(parse_command("/shell reset"), Ok(Command::ShellReset));
+    }
+
+    #[test]
+    fn rejects_wrong_arguments() {
+        assert_eq!(parse_command("/quit now"), Err(CommandError::Usage("/quit")));
+        assert_eq!(parse_command("/load"), Err(CommandError::Usage("/load <name>")));
+        assert_eq!(parse_command("/shell restart"), Err(CommandError::Usage("/shell reset")));
+        assert_eq!(parse_command("!"), Err(CommandError::Usage("!<command>")));
+    }
+
+    #[test]
+    fn suggests_unknown_commands() {
+        assert_eq!(
+            parse_command("/sess"),
+            Err(CommandError::Unknown {
+                name: String::from("sess"),
+                suggestion: Some("sessions"),
+            })
+        );
+
+        let error = parse_command("/frobnicate").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Unknown command /frobnicate. Type /help to see the available commands."
+        );
+    }
+
+    #[test]
+    fn parses_shell_commands() {
+        assert_eq!(
+            parse_command("!  ls -la "),
+            Ok(Command::ShellCommand(String::from("ls -la")))
+        );
+    }
+
+    #[test]
+    fn treats_everything_else_as_a_message() {
+        for input in ["What does /etc/hosts do?", "/etc/hosts is empty", "hello /quit"] {
+            assert_eq!(parse_command(input), Ok(Command::ChatMessage(input.to_string())));
+        }
+    }
+}
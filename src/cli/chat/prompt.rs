@@ -1,13 +1,172 @@
-use rustyline::{Config, Editor, Result};
+use std::borrow::Cow;
+
+use crossterm::style::Stylize;
+use rustyline::completion::{Completer, FilenameCompleter, Pair};
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::validate::{ValidationContext, ValidationResult, Validator};
+use rustyline::{Config, Context, Editor, Helper, Result};
+
+use super::command::{Arity, CommandSpec, COMMANDS};
+use super::input_source;
+use super::session;
 
 pub fn generate_prompt(custom_prompt: Option<&str>) -> String {
     custom_prompt.unwrap_or("> ").to_string()
 }
 
-pub fn rl() -> Result<Editor<()>> {
+pub fn rl(max_history_size: usize) -> Result<Editor<ChatHelper>> {
     let config = Config::builder()
         .history_ignore_space(true)
+        .history_ignore_dups(true)
+        .max_history_size(max_history_size)
         .completion_type(rustyline::CompletionType::List)
+        .bracketed_paste(true)
         .build();
-    Editor::with_config(config)
+    let mut editor = Editor::with_config(config)?;
+    editor.set_helper(Some(ChatHelper::new()));
+    Ok(editor)
+}
+
+/// Completion, hints and multi-line validation for the REPL.
+///
+/// Completes slash command names and their arguments, `@file` references
+/// anywhere in a message and paths in `!` shell commands. While a command is
+/// being typed its arguments are hinted in grey.
+pub struct ChatHelper {
+    filename_completer: FilenameCompleter,
+}
+
+impl ChatHelper {
+    pub fn new() -> Self {
+        Self {
+            filename_completer: FilenameCompleter::new(),
+        }
+    }
+
+    /// Complete the name of a slash command
+    fn complete_command(&self, prefix: &str) -> Vec<Pair> {
+        COMMANDS
+            .iter()
+            .filter(|spec| spec.name.starts_with(prefix))
+            .map(|spec| Pair {
+                display: spec.usage.to_string(),
+                replacement: match spec.arity {
+                    Arity::None => format!("/{}", spec.name),
+                    _ => format!("/{} ", spec.name),
+                },
+            })
+            .collect()
+    }
+
+    /// Complete the argument of a slash command, for the commands whose
+    /// arguments are known in advance
+    fn complete_argument(&self, command: &str, prefix: &str) -> Vec<Pair> {
+        let candidates: Vec<String> = match command {
+            "help" => COMMANDS.iter().map(|spec| spec.name.to_string()).collect(),
+            "shell" => vec![String::from("reset")],
+            "load" | "save" => session::list()
+                .map(|sessions| sessions.into_iter().map(|session| session.name).collect())
+                .unwrap_or_default(),
+            _ => Vec::new(),
+        };
+
+        candidates
+            .into_iter()
+            .filter(|candidate| candidate.starts_with(prefix))
+            .map(|candidate| Pair {
+                display: candidate.clone(),
+                replacement: candidate,
+            })
+            .collect()
+    }
+}
+
+impl Default for ChatHelper {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Completer for ChatHelper {
+    type Candidate = Pair;
+
+    fn complete(&self, line: &str, pos: usize, _ctx: &Context<'_>) -> Result<(usize, Vec<Pair>)> {
+        let before = &line[..pos];
+
+        if let Some(command_line) = before.strip_prefix('/') {
+            return Ok(match command_line.split_once(char::is_whitespace) {
+                None => (0, self.complete_command(command_line)),
+                Some((command, argument)) if !argument.trim_start().contains(char::is_whitespace) => {
+                    let argument = argument.trim_start();
+                    (pos - argument.len(), self.complete_argument(command, argument))
+                }
+                Some(_) => (pos, Vec::new()),
+            });
+        }
+
+        // Paths anywhere in a shell command
+        if before.starts_with('!') {
+            return self.filename_completer.complete_path(line, pos);
+        }
+
+        // `@file` references in a chat message
+        let word_start = before.rfind(char::is_whitespace).map(|i| i + 1).unwrap_or(0);
+        if let Some(path) = before[word_start..].strip_prefix('@') {
+            let (start, candidates) = self.filename_completer.complete_path(path, path.len())?;
+            return Ok((word_start + 1 + start, candidates));
+        }
+
+        Ok((pos, Vec::new()))
+    }
+}
+
+impl Hinter for ChatHelper {
+    type Hint = String;
+
+    fn hint(&self, line: &str, pos: usize, _ctx: &Context<'_>) -> Option<String> {
+        if pos < line.len() {
+            return None;
+        }
+        let command_line = line.strip_prefix('/')?;
+
+        // Complete a uniquely typed command name, followed by its arguments
+        if !command_line.contains(char::is_whitespace) {
+            let mut matches = COMMANDS.iter().filter(|spec| spec.name.starts_with(command_line));
+            let spec = matches.next()?;
+            if matches.next().is_some() {
+                return None;
+            }
+            return Some(spec.usage[command_line.len() + 1..].to_string()).filter(|hint| !hint.is_empty());
+        }
+
+        // Hint the arguments after the command name until one is typed
+        let (name, argument) = command_line.split_once(char::is_whitespace)?;
+        let spec = CommandSpec::find(name)?;
+        if !argument.trim().is_empty() {
+            return None;
+        }
+        let arguments = spec.usage[spec.name.len() + 1..].trim_start();
+        Some(arguments.to_string()).filter(|hint| !hint.is_empty())
+    }
+}
+
+impl Highlighter for ChatHelper {
+    fn highlight_hint<'h>(&self, hint: &'h str) -> Cow<'h, str> {
+        Cow::Owned(hint.dark_grey().to_string())
+    }
 }
+
+impl Validator for ChatHelper {
+    /// Input continues on the next line while `input_source::is_incomplete`.
+    /// Pasted text never submits early, as bracketed paste keeps its
+    /// newlines in the buffer.
+    fn validate(&self, ctx: &mut ValidationContext) -> Result<ValidationResult> {
+        if input_source::is_incomplete(ctx.input()) {
+            return Ok(ValidationResult::Incomplete);
+        }
+        Ok(ValidationResult::Valid(None))
+    }
+}
+
+impl Helper for ChatHelper {}
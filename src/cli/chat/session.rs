@@ -0,0 +1,140 @@
+use std::cmp::Reverse;
+use std::fs;
+use std::path::PathBuf;
+
+use chrono::{DateTime, Local, NaiveDateTime, Utc};
+use eyre::{Result, eyre};
+use serde::{Deserialize, Serialize};
+
+use crate::model_provider::Content;
+
+/// A conversation saved to disk, including tool calls and their results
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Session {
+    pub name: String,
+    pub saved_at: DateTime<Utc>,
+    pub messages: Vec<Content>,
+}
+
+/// Number of sessions with generated names that are kept, as every
+/// interactive chat is saved under one when it ends
+pub const MAX_GENERATED: usize = 20;
+
+/// Format of generated session names
+const GENERATED_NAME_FORMAT: &str = "%Y-%m-%d_%H-%M-%S";
+
+/// Summary of a saved session for listing
+pub struct SessionInfo {
+    pub name: String,
+    pub saved_at: DateTime<Utc>,
+    pub message_count: usize,
+}
+
+/// Directory where sessions are stored, e.g. `~/.local/share/gemini-chat-cli/sessions`
+pub fn sessions_dir() -> Result<PathBuf> {
+    let data_dir = dirs::data_dir().ok_or_else(|| eyre!("Could not determine the data directory"))?;
+    Ok(data_dir.join("gemini-chat-cli").join("sessions"))
+}
+
+/// Generate a session name from the current local time
+pub fn generate_name() -> String {
+    Local::now().format(GENERATED_NAME_FORMAT).to_string()
+}
+
+/// Whether `name` was made by `generate_name` rather than chosen by the user
+pub fn is_generated_name(name: &str) -> bool {
+    NaiveDateTime::parse_from_str(name, GENERATED_NAME_FORMAT).is_ok()
+}
+
+/// Delete all but the `keep` most recently saved sessions with generated
+/// names. Sessions the user named are never deleted.
+pub fn prune_generated(keep: usize) -> Result<()> {
+    let dir = sessions_dir()?;
+    for info in list()?.into_iter().filter(|info| is_generated_name(&info.name)).skip(keep) {
+        let path = dir.join(format!("{}.json", info.name));
+        fs::remove_file(&path).map_err(|e| eyre!("Failed to delete session {}: {}", path.display(), e))?;
+    }
+    Ok(())
+}
+
+/// Save a conversation under `name`, replacing any session with the same name
+pub fn save(name: &str, messages: &[Content]) -> Result<PathBuf> {
+    validate_name(name)?;
+    
+    let dir = sessions_dir()?;
+    fs::create_dir_all(&dir)
+        .map_err(|e| eyre!("Failed to create directory {}: {}", dir.display(), e))?;
+    
+    let session = Session {
+        name: name.to_string(),
+        saved_at: Utc::now(),
+        messages: messages.to_vec(),
+    };
+    
+    let path = dir.join(format!("{}.json", name));
+    fs::write(&path, serde_json::to_string_pretty(&session)?)
+        .map_err(|e| eyre!("Failed to write session {}: {}", path.display(), e))?;
+    
+    Ok(path)
+}
+
+/// Load the session saved under `name`
+pub fn load(name: &str) -> Result<Session> {
+    validate_name(name)?;
+    
+    let path = sessions_dir()?.join(format!("{}.json", name));
+    if !path.exists() {
+        return Err(eyre!("No session named '{}'. Use /sessions to list saved sessions.", name));
+    }
+    
+    let content = fs::read_to_string(&path)
+        .map_err(|e| eyre!("Failed to read session {}: {}", path.display(), e))?;
+    serde_json::from_str(&content)
+        .map_err(|e| eyre!("Session {} is corrupted: {}", path.display(), e))
+}
+
+/// List saved sessions, most recently saved first
+pub fn list() -> Result<Vec<SessionInfo>> {
+    let dir = sessions_dir()?;
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+    
+    let mut sessions = Vec::new();
+    for entry in fs::read_dir(&dir)?.flatten() {
+        let path = entry.path();
+        if path.extension().and_then(|e| e.to_str()) != Some("json") {
+            continue;
+        }
+        
+        // Skip files that can't be parsed rather than failing the whole listing
+        let Ok(content) = fs::read_to_string(&path) else { continue };
+        let Ok(session) = serde_json::from_str::<Session>(&content) else { continue };
+        
+        sessions.push(SessionInfo {
+            name: session.name,
+            saved_at: session.saved_at,
+            message_count: session.messages.len(),
+        });
+    }
+    
+    sessions.sort_by_key(|session| Reverse(session.saved_at));
+    Ok(sessions)
+}
+
+/// Name of the most recently saved session, if any
+pub fn latest() -> Result<Option<String>> {
+    Ok(list()?.into_iter().next().map(|info| info.name))
+}
+
+fn validate_name(name: &str) -> Result<()> {
+    let valid = !name.is_empty()
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+        && !name.starts_with('.');
+    
+    if valid {
+        Ok(())
+    } else {
+        Err(eyre!("Invalid session name '{}'. Use letters, digits, '-', '_' and '.'", name))
+    }
+}
@@ -1,21 +1,94 @@
-use std::process::Command;
+use std::time::Duration;
 
+use async_trait::async_trait;
 use eyre::{Result, eyre};
+use serde::{Deserialize, Serialize};
 
-/// Execute a bash command and return its output.
+use super::shell_session::combine;
+use super::{Tool, ToolContext, ToolOutput};
+
+/// How long a command may run before it is killed, unless configured
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Progress of a running command, to show it while it runs
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CommandEvent {
+    /// A line of stdout or stderr, without the line break
+    Line(String),
+    /// The command failed because the sandbox blocked it, for this reason
+    Blocked(String),
+    /// The command exited, with its exit code unless a signal killed it
+    Exited { code: Option<i32>, elapsed: Duration },
+}
+
+/// Result of a command that ran to completion, as sent to the model
+#[derive(Debug, Clone, Serialize)]
+pub struct CommandOutput {
+    /// Exit code, or `None` if a signal ended the command
+    pub exit_code: Option<i32>,
+    pub stdout: String,
+    pub stderr: String,
+    /// Whether the middle of stdout was left out to keep it within the size limit
+    pub stdout_truncated: bool,
+    pub stderr_truncated: bool,
+    /// Run time in seconds, to the millisecond
+    pub duration_secs: f64,
+    /// Why the command failed, if the sandbox blocked it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub blocked_by_sandbox: Option<String>,
+}
+
+impl CommandOutput {
+    /// Stdout followed by stderr, for showing both to the user
+    pub fn combined(&self) -> String {
+        combine(&self.stdout, &self.stderr)
+    }
+}
+
+/// Arguments of the `execute_bash` tool
+#[derive(Debug, Clone, Deserialize)]
+pub struct ExecuteBash {
+    pub command: String,
+}
+
+#[async_trait]
+impl Tool for ExecuteBash {
+    fn validate(&self) -> Result<()> {
+        if self.command.trim().is_empty() {
+            return Err(eyre!("Command cannot be empty"));
+        }
+        Ok(())
+    }
+    
+    async fn execute(&self, context: &ToolContext) -> Result<ToolOutput> {
+        let output = execute_bash(&self.command, context).await?;
+        Ok(ToolOutput::json(serde_json::to_value(output)?))
+    }
+    
+    fn describe(&self) -> String {
+        format!("I will run the following shell command:\n\n  {}\n", self.command)
+    }
+}
+
+/// Execute a bash command in the chat's shell session and return its exit
+/// code and output.
 ///
-/// This function runs the provided command in a bash shell and captures
-/// both stdout and stderr output. It handles command execution in a secure
-/// manner and provides detailed error information if the command fails.
+/// The session keeps the working directory and environment between
+/// commands. See `ShellSession::run` for how the command is run, timed out
+/// and cancelled. Output lines and the exit are sent to the context's
+/// `events` as they happen.
 ///
 /// # Arguments
 ///
 /// * `command` - The bash command to execute as a string
+/// * `context` - The shell session, timeout and where to send progress
 ///
 /// # Returns
 ///
-/// A string containing the combined stdout and stderr output of the command,
-/// or an error if the command execution failed.
+/// The exit code, stdout and stderr of the command, or an error if the
+/// command could not run to completion. A failing command is not an error.
+/// Each stream is capped at half of `MAX_TOOL_RESPONSE_SIZE`, keeping its
+/// beginning and end.
 ///
 /// # Security Considerations
 ///
@@ -26,10 +99,10 @@ use eyre::{Result, eyre};
 /// # Examples
 ///
 /// ```
-/// let result = execute_bash("ls -la").await?;
-/// println!("{}", result);
+/// let output = execute_bash("ls -la", &context).await?;
+/// println!("{}", output.stdout);
 /// ```
-pub async fn execute_bash(command: &str) -> Result<String> {
+pub async fn execute_bash(command: &str, context: &ToolContext) -> Result<CommandOutput> {
     if command.trim().is_empty() {
         return Err(eyre!("Command cannot be empty"));
     }
@@ -37,47 +110,8 @@ pub async fn execute_bash(command: &str) -> Result<String> {
     // Log the command being executed (for debugging purposes)
     tracing::debug!("Executing bash command: {}", command);
 
-    // Execute the command using bash
-    let output = Command::new("bash")
-        .arg("-c")
-        .arg(command)
-        .output()
-        .map_err(|e| eyre!("Failed to execute command: {}", e))?;
-
-    // Combine stdout and stderr
-    let mut result = String::new();
-    
-    // Add stdout if not empty
-    if !output.stdout.is_empty() {
-        result.push_str(&String::from_utf8_lossy(&output.stdout));
-    }
-    
-    // Add stderr if not empty (with a prefix to distinguish it)
-    if !output.stderr.is_empty() {
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        
-        // If we already have stdout content, add a separator
-        if !result.is_empty() && !result.ends_with('\n') {
-            result.push('\n');
-        }
-        
-        // Add stderr with a prefix if the command failed
-        if !output.status.success() {
-            result.push_str("Error: ");
-        }
-        
-        result.push_str(&stderr);
-    }
-    
-    // If the command failed and there's no output, provide a generic error message
-    if !output.status.success() && result.is_empty() {
-        result = format!("Command failed with exit code: {}", output.status);
-    }
-    
-    // Ensure the result ends with a newline for better formatting
-    if !result.is_empty() && !result.ends_with('\n') {
-        result.push('\n');
-    }
-    
-    Ok(result)
+    context
+        .shell
+        .run(command, context.bash_timeout, context.events.as_ref())
+        .await
 }
@@ -2,7 +2,117 @@ use std::fs;
 use std::path::Path;
 use std::time::{SystemTime, UNIX_EPOCH};
 
+use async_trait::async_trait;
 use eyre::{Result, eyre};
+use serde::Deserialize;
+
+use super::{Tool, ToolContext, ToolOutput, sanitize_path};
+
+/// Arguments of the `fs_read` tool, selected by the `mode` field
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "mode")]
+pub enum FsRead {
+    #[serde(alias = "line")]
+    Line(FsLine),
+    #[serde(alias = "directory")]
+    Directory(FsDirectory),
+    #[serde(alias = "search")]
+    Search(FsSearch),
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct FsLine {
+    pub path: String,
+    pub start_line: Option<i32>,
+    pub end_line: Option<i32>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct FsDirectory {
+    pub path: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct FsSearch {
+    pub path: String,
+    pub pattern: String,
+    pub context_lines: Option<usize>,
+}
+
+impl FsRead {
+    fn path(&self) -> String {
+        match self {
+            FsRead::Line(line) => line.path.clone(),
+            FsRead::Directory(directory) => directory.path.clone(),
+            FsRead::Search(search) => search.path.clone(),
+        }
+    }
+    
+    fn path_mut(&mut self) -> &mut String {
+        match self {
+            FsRead::Line(line) => &mut line.path,
+            FsRead::Directory(directory) => &mut directory.path,
+            FsRead::Search(search) => &mut search.path,
+        }
+    }
+}
+
+#[async_trait]
+impl Tool for FsRead {
+    fn validate(&self) -> Result<()> {
+        match self {
+            FsRead::Line(FsLine { path, .. }) | FsRead::Directory(FsDirectory { path }) if path.trim().is_empty() => {
+                Err(eyre!("Path cannot be empty"))
+            }
+            FsRead::Search(search) if search.pattern.is_empty() => Err(eyre!("Search pattern cannot be empty")),
+            _ => Ok(()),
+        }
+    }
+    
+    async fn execute(&self, _context: &ToolContext) -> Result<ToolOutput> {
+        let path = self.path();
+        
+        let result = match self {
+            FsRead::Line(line) => {
+                read_file_lines(&path, line.start_line.unwrap_or(1), line.end_line.unwrap_or(-1)).await
+            }
+            FsRead::Directory(_) => list_directory(&path).await,
+            FsRead::Search(search) => search_file(&path, &search.pattern, search.context_lines).await,
+        };
+        
+        // If there's an error and it's about a file not found, list the directory
+        // to help the model understand what files are available
+        if let Err(e) = &result {
+            if e.to_string().contains("not found") {
+                let dir_path = Path::new(&path).parent().unwrap_or(Path::new("."));
+                if let Ok(dir_listing) = list_directory(dir_path.to_str().unwrap_or(".")).await {
+                    return Ok(ToolOutput::text(format!("Error: {}.\n\nAvailable files in directory:\n{}", e, dir_listing)));
+                }
+            }
+        }
+        
+        result.map(ToolOutput::text)
+    }
+    
+    fn describe(&self) -> String {
+        match self {
+            FsRead::Line(_) => format!("Reading file: {}", self.path()),
+            FsRead::Directory(_) => format!("Listing directory: {}", self.path()),
+            FsRead::Search(search) => format!("Searching {} for '{}'", self.path(), search.pattern),
+        }
+    }
+    
+    fn resolve_paths(&mut self, dir: &Path) {
+        let path = self.path_mut();
+        if !path.trim().is_empty() {
+            *path = sanitize_path(path, dir).to_string_lossy().to_string();
+        }
+    }
+    
+    fn requires_approval(&self) -> bool {
+        false
+    }
+}
 
 /// Read lines from a file.
 ///
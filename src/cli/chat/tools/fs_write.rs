@@ -2,7 +2,132 @@ use std::fs;
 use std::io::Write;
 use std::path::Path;
 
+use async_trait::async_trait;
+use crossterm::style::Stylize;
 use eyre::{Result, eyre};
+use serde::Deserialize;
+use similar::{ChangeTag, TextDiff};
+
+use super::{Tool, ToolContext, ToolOutput, sanitize_path};
+
+/// Arguments of the `fs_write` tool, selected by the `command` field
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "command", rename_all = "snake_case")]
+pub enum FsWrite {
+    Create {
+        path: String,
+        file_text: String,
+    },
+    StrReplace {
+        path: String,
+        old_str: String,
+        new_str: String,
+    },
+    Insert {
+        path: String,
+        insert_line: usize,
+        new_str: String,
+    },
+    Append {
+        path: String,
+        new_str: String,
+    },
+}
+
+impl FsWrite {
+    fn path(&self) -> String {
+        match self {
+            FsWrite::Create { path, .. }
+            | FsWrite::StrReplace { path, .. }
+            | FsWrite::Insert { path, .. }
+            | FsWrite::Append { path, .. } => path.clone(),
+        }
+    }
+    
+    fn path_mut(&mut self) -> &mut String {
+        match self {
+            FsWrite::Create { path, .. }
+            | FsWrite::StrReplace { path, .. }
+            | FsWrite::Insert { path, .. }
+            | FsWrite::Append { path, .. } => path,
+        }
+    }
+    
+    /// Compute the content the file will have after the write, without
+    /// touching the file system
+    fn proposed_content(&self, current: &str) -> String {
+        match self {
+            FsWrite::Create { file_text, .. } => file_text.clone(),
+            FsWrite::StrReplace { old_str, new_str, .. } => current.replace(old_str.as_str(), new_str),
+            FsWrite::Insert { insert_line, new_str, .. } => {
+                let mut lines: Vec<&str> = current.lines().collect();
+                lines.insert((*insert_line).min(lines.len()), new_str);
+                lines.join("\n")
+            }
+            FsWrite::Append { new_str, .. } => {
+                let mut content = current.to_string();
+                if !content.is_empty() && !content.ends_with('\n') {
+                    content.push('\n');
+                }
+                content.push_str(new_str);
+                if !content.ends_with('\n') {
+                    content.push('\n');
+                }
+                content
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl Tool for FsWrite {
+    fn validate(&self) -> Result<()> {
+        let path = self.path();
+        if path.trim().is_empty() {
+            return Err(eyre!("Path cannot be empty"));
+        }
+        
+        match self {
+            FsWrite::Create { .. } => Ok(()),
+            _ if !Path::new(&path).exists() => Err(eyre!("File not found: {}", path)),
+            FsWrite::StrReplace { old_str, .. } if old_str.is_empty() => Err(eyre!("old_str cannot be empty")),
+            _ => Ok(()),
+        }
+    }
+    
+    async fn execute(&self, context: &ToolContext) -> Result<ToolOutput> {
+        let path = self.path();
+        if let Some(sandbox) = context.shell.sandbox() {
+            if !sandbox.allows_write(Path::new(&path)) {
+                return Err(sandbox.write_error(Path::new(&path)));
+            }
+        }
+        
+        let result = match self {
+            FsWrite::Create { file_text, .. } => create_file(&path, file_text).await?,
+            FsWrite::StrReplace { old_str, new_str, .. } => replace_in_file(&path, old_str, new_str).await?,
+            FsWrite::Insert { insert_line, new_str, .. } => insert_in_file(&path, *insert_line, new_str).await?,
+            FsWrite::Append { new_str, .. } => append_to_file(&path, new_str).await?,
+        };
+        
+        Ok(ToolOutput::text(result))
+    }
+    
+    fn describe(&self) -> String {
+        let path = self.path();
+        let current = fs::read_to_string(&path).unwrap_or_default();
+        let proposed = self.proposed_content(&current);
+        
+        format!("I will modify {}:\n\n{}", path, format_diff(&current, &proposed))
+    }
+
+    fn resolve_paths(&mut self, dir: &Path) {
+        let path = self.path_mut();
+        if !path.trim().is_empty() {
+            *path = sanitize_path(path, dir).to_string_lossy().to_string();
+        }
+    }
+}
 
 /// Create a new file with the specified content.
 ///
@@ -138,12 +263,13 @@ pub async fn append_to_file(path: &str, content: &str) -> Result<String> {
     Ok(format!("Content appended successfully to {}", path.display()))
 }
 
-/// Insert content at a specific line in a file.
+/// Insert content after a specific line in a file.
 ///
 /// # Arguments
 ///
 /// * `path` - Path to the file to modify
-/// * `line_number` - Line number to insert at (1-based index)
+/// * `line_number` - Line to insert after (1-based index), or 0 to insert
+///   before the first line
 /// * `content` - Content to insert
 ///
 /// # Returns
@@ -168,7 +294,7 @@ pub async fn insert_in_file(path: &str, line_number: usize, content: &str) -> Re
         .map_err(|e| eyre!("Failed to read file {}: {}", path.display(), e))?;
     
     // Split the content into lines
-    let lines: Vec<&str> = file_content.lines().collect();
+    let mut lines: Vec<&str> = file_content.lines().collect();
     
     // Check if the line number is valid
     if line_number > lines.len() {
@@ -176,21 +302,99 @@ pub async fn insert_in_file(path: &str, line_number: usize, content: &str) -> Re
                        line_number, lines.len()));
     }
     
-    // Insert the content at the specified line
-    let mut new_lines = Vec::new();
-    for (i, &line) in lines.iter().enumerate() {
-        new_lines.push(line);
-        if i + 1 == line_number {
-            new_lines.push(content);
-        }
-    }
+    // Inserting after line n puts the content at index n
+    lines.insert(line_number, content);
     
     // Join the lines back together
-    let new_content = new_lines.join("\n");
+    let new_content = lines.join("\n");
     
     // Write the modified content back to the file
     fs::write(path, new_content)
         .map_err(|e| eyre!("Failed to write to file {}: {}", path.display(), e))?;
     
-    Ok(format!("Content inserted successfully at line {} in {}", line_number, path.display()))
+    Ok(format!("Content inserted successfully after line {} in {}", line_number, path.display()))
+}
+
+/// Format the difference between the current and proposed content of a file.
+///
+/// Only changed lines and a few lines of surrounding context are shown, with
+/// removals in red and additions in green.
+///
+/// # Arguments
+///
+/// * `old_content` - Current content of the file (empty for new files)
+/// * `new_content` - Content the file will have after the write
+///
+/// # Returns
+///
+/// A colored, unified-style diff suitable for printing to the terminal.
+pub fn format_diff(old_content: &str, new_content: &str) -> String {
+    let diff = TextDiff::from_lines(old_content, new_content);
+    let mut result = String::new();
+    
+    for (i, group) in diff.grouped_ops(3).iter().enumerate() {
+        if i > 0 {
+            result.push_str(&format!("{}\n", "...".dark_grey()));
+        }
+        
+        for op in group {
+            for change in diff.iter_changes(op) {
+                let line = change.to_string_lossy();
+                let line = line.trim_end_matches('\n');
+                let formatted = match change.tag() {
+                    ChangeTag::Delete => format!("- {}", line).red().to_string(),
+                    ChangeTag::Insert => format!("+ {}", line).green().to_string(),
+                    ChangeTag::Equal => format!("  {}", line),
+                };
+                result.push_str(&formatted);
+                result.push('\n');
+            }
+        }
+    }
+    
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::*;
+
+    /// Insert into a new file with the given content and return the result,
+    /// checking that it matches what the approval diff showed
+    async fn insert(name: &str, content: &str, insert_line: usize) -> String {
+        let path = env::temp_dir().join(format!("gemini-chat-insert-{}-{}", std::process::id(), name));
+        fs::write(&path, content).unwrap();
+        let path = path.to_string_lossy().to_string();
+
+        let tool = FsWrite::Insert { path: path.clone(), insert_line, new_str: String::from("new") };
+        let proposed = tool.proposed_content(content);
+        insert_in_file(&path, insert_line, "new").await.unwrap();
+
+        let written = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(written, proposed);
+        written
+    }
+
+    #[tokio::test]
+    async fn insert_at_line_zero_goes_before_the_first_line() {
+        assert_eq!(insert("zero", "one\ntwo\n", 0).await, "new\none\ntwo");
+    }
+
+    #[tokio::test]
+    async fn insert_goes_after_the_given_line() {
+        assert_eq!(insert("after", "one\ntwo\n", 1).await, "one\nnew\ntwo");
+        assert_eq!(insert("last", "one\ntwo\n", 2).await, "one\ntwo\nnew");
+    }
+
+    #[tokio::test]
+    async fn insert_past_the_end_fails() {
+        let path = env::temp_dir().join(format!("gemini-chat-insert-{}-past", std::process::id()));
+        fs::write(&path, "one\n").unwrap();
+        let result = insert_in_file(&path.to_string_lossy(), 2, "new").await;
+        fs::remove_file(&path).unwrap();
+        assert!(result.is_err());
+    }
 }
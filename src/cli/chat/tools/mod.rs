@@ -1,22 +1,66 @@
 pub mod execute_bash;
 pub mod fs_read;
 pub mod fs_write;
+pub mod sandbox;
+pub mod shell_session;
 pub mod use_aws;
 
+use std::collections::{BTreeMap, HashMap};
 use std::path::{Path, PathBuf};
+use std::time::Duration;
 
-use eyre::Result;
+use async_trait::async_trait;
+use eyre::{Result, eyre};
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tokio::sync::mpsc::UnboundedSender;
+
+use crate::model_provider::{FunctionCall, ToolDefinition};
+use execute_bash::{CommandEvent, ExecuteBash};
+use fs_read::FsRead;
+use fs_write::FsWrite;
+use shell_session::ShellSession;
+use use_aws::UseAws;
 
 /// Maximum size in bytes for tool responses to prevent excessive output
 pub const MAX_TOOL_RESPONSE_SIZE: usize = 1_000_000;
 
+/// Tool schemas advertised to the model, keyed by tool name
+const TOOL_INDEX: &str = include_str!("tool_index.json");
+
 /// Represents the output of a tool invocation
 #[derive(Debug, Clone, Serialize)]
 pub struct ToolOutput {
     pub output: OutputKind,
 }
 
+impl ToolOutput {
+    /// Create a plain text output
+    pub fn text(text: impl Into<String>) -> Self {
+        Self {
+            output: OutputKind::Text(text.into()),
+        }
+    }
+    
+    /// Create a structured output
+    pub fn json(value: Value) -> Self {
+        Self {
+            output: OutputKind::Json(value),
+        }
+    }
+    
+    /// Convert the output into the JSON object sent back to the model.
+    /// Objects are sent as they are, anything else is wrapped in one.
+    pub fn into_response(self) -> Value {
+        match self.output {
+            OutputKind::Json(value @ Value::Object(_)) => value,
+            OutputKind::Json(value) => json!({ "output": value }),
+            OutputKind::Text(text) | OutputKind::Binary(text) => json!({ "output": text }),
+        }
+    }
+}
+
 /// Different kinds of output that tools can produce
 #[derive(Debug, Clone, Serialize)]
 #[serde(tag = "type")]
@@ -31,6 +75,20 @@ pub enum OutputKind {
     Binary(String),
 }
 
+/// Settings that tools are executed with, besides their arguments
+#[derive(Debug, Clone)]
+pub struct ToolContext {
+    /// How long a shell command or AWS CLI call may run before it is killed
+    pub bash_timeout: Duration,
+
+    /// Where shell commands report their output while they run
+    pub events: Option<UnboundedSender<CommandEvent>>,
+
+    /// The shell that `execute_bash` runs commands in. Its working
+    /// directory is where relative paths of all tools start from.
+    pub shell: ShellSession,
+}
+
 /// Specification for a tool that can be invoked
 #[derive(Debug, Clone, Deserialize)]
 pub struct ToolSpec {
@@ -41,35 +99,142 @@ pub struct ToolSpec {
     pub description: String,
     
     /// Parameters required by the tool
+    #[serde(alias = "input_schema")]
     pub parameters: serde_json::Value,
 }
 
 /// Trait for tools that can be invoked
-pub trait Tool {
+#[async_trait]
+pub trait Tool: Send + Sync {
     /// Validate the tool parameters before execution
     fn validate(&self) -> Result<()>;
     
     /// Execute the tool and return its output
-    fn execute(&self) -> Result<ToolOutput>;
+    async fn execute(&self, context: &ToolContext) -> Result<ToolOutput>;
     
     /// Get a description of what the tool will do
     fn describe(&self) -> String;
+    
+    /// Whether the user must approve the invocation before it runs.
+    /// Tools that only read state can return `false`.
+    fn requires_approval(&self) -> bool {
+        true
+    }
+    
+    /// Make relative paths in the arguments absolute, relative to `dir`.
+    /// Called once after parsing, with the shell's working directory, so
+    /// that paths mean the same to every tool.
+    fn resolve_paths(&mut self, _dir: &Path) {}
+    
+    /// Whether the user must approve every invocation, even under `--yes`
+    /// or after choosing to always allow the tool
+    fn requires_explicit_approval(&self) -> bool {
+        false
+    }
+}
+
+/// Builds a tool from the arguments of a function call
+type ToolConstructor = fn(Value) -> Result<Box<dyn Tool>>;
+
+fn construct<T: Tool + DeserializeOwned + 'static>(args: Value) -> Result<Box<dyn Tool>> {
+    Ok(Box::new(serde_json::from_value::<T>(args)?))
+}
+
+/// The set of tools available to the model.
+///
+/// Schemas come from `tool_index.json` and each registered name maps to a
+/// `Tool` type that is deserialized directly from the model's arguments.
+pub struct ToolRegistry {
+    specs: BTreeMap<String, ToolSpec>,
+    constructors: HashMap<String, ToolConstructor>,
+}
+
+impl ToolRegistry {
+    /// Create a registry with all built-in tools
+    pub fn new() -> Result<Self> {
+        let specs: BTreeMap<String, ToolSpec> = serde_json::from_str(TOOL_INDEX)?;
+        
+        let mut registry = Self {
+            specs,
+            constructors: HashMap::new(),
+        };
+        
+        registry.register::<ExecuteBash>("execute_bash")?;
+        registry.register::<FsRead>("fs_read")?;
+        registry.register::<FsWrite>("fs_write")?;
+        registry.register::<UseAws>("use_aws")?;
+        
+        Ok(registry)
+    }
+    
+    /// Register a tool type under a name that has a schema in `tool_index.json`
+    pub fn register<T: Tool + DeserializeOwned + 'static>(&mut self, name: &str) -> Result<()> {
+        if !self.specs.contains_key(name) {
+            return Err(eyre!("No schema for tool '{}' in tool_index.json", name));
+        }
+        self.constructors.insert(name.to_string(), construct::<T>);
+        Ok(())
+    }
+    
+    /// Keep only the named tools, e.g. those enabled in the config file
+    pub fn retain(&mut self, names: &[String]) -> Result<()> {
+        if let Some(unknown) = names.iter().find(|name| !self.constructors.contains_key(*name)) {
+            return Err(eyre!("Unknown tool '{}' in config", unknown));
+        }
+        self.constructors.retain(|name, _| names.contains(name));
+        Ok(())
+    }
+    
+    /// Names of all registered tools, in a stable order
+    pub fn names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.constructors.keys().cloned().collect();
+        names.sort();
+        names
+    }
+    
+    /// Definitions of all registered tools, to advertise to the model
+    pub fn definitions(&self) -> Vec<ToolDefinition> {
+        self.specs
+            .values()
+            .filter(|spec| self.constructors.contains_key(&spec.name))
+            .map(|spec| ToolDefinition {
+                name: spec.name.clone(),
+                description: spec.description.clone(),
+                parameters: spec.parameters.clone(),
+            })
+            .collect()
+    }
+    
+    /// Build the tool requested by a function call from its arguments, with
+    /// relative paths resolved against `dir`
+    pub fn parse(&self, call: &FunctionCall, dir: &Path) -> Result<Box<dyn Tool>> {
+        let constructor = self
+            .constructors
+            .get(&call.name)
+            .ok_or_else(|| eyre!("Unknown tool: {}", call.name))?;
+        
+        let mut tool = constructor(call.args.clone()).map_err(|e| eyre!("Invalid arguments for {}: {}", call.name, e))?;
+        tool.resolve_paths(dir);
+        Ok(tool)
+    }
 }
 
 /// Sanitize a path argument from a tool call
 ///
 /// This function ensures that paths are properly resolved and normalized.
 /// It expands home directory references (~) and converts relative paths
-/// to absolute paths based on the current working directory.
+/// to absolute paths based on `base_dir`.
 ///
 /// # Arguments
 ///
 /// * `path` - The path string to sanitize
+/// * `base_dir` - The directory relative paths start from, usually the
+///   working directory of the shell session
 ///
 /// # Returns
 ///
 /// A sanitized PathBuf
-pub fn sanitize_path(path: &str) -> PathBuf {
+pub fn sanitize_path(path: &str, base_dir: &Path) -> PathBuf {
     let path = path.trim();
     
     // Expand home directory if path starts with ~
@@ -86,9 +251,7 @@ pub fn sanitize_path(path: &str) -> PathBuf {
     // Convert to absolute path if relative
     let path_buf = Path::new(path);
     if path_buf.is_relative() {
-        if let Ok(current_dir) = std::env::current_dir() {
-            return current_dir.join(path_buf);
-        }
+        return base_dir.join(path_buf);
     }
     
     path_buf.to_path_buf()
@@ -0,0 +1,172 @@
+use std::ffi::OsString;
+use std::io::ErrorKind;
+use std::path::{Component, Path, PathBuf};
+
+use eyre::{Result, bail, eyre};
+use tokio::process::Command;
+
+/// Program that sets up the sandbox
+const BWRAP: &str = "bwrap";
+
+/// Error messages of commands that tried to use the network without it
+const NETWORK_ERRORS: &[&str] = &[
+    "network is unreachable",
+    "could not resolve host",
+    "temporary failure in name resolution",
+    "name or service not known",
+    "failed to lookup address",
+];
+
+/// Restrictions on the tools, under `--sandbox`.
+///
+/// The shell and the AWS CLI run under bubblewrap: the file system is
+/// read-only except for the workspace, the configured writable paths and a
+/// private `/tmp`, and the network is off unless the policy allows it.
+/// `fs_write` only writes to the workspace and the writable paths, while
+/// `fs_read` can read everything, like the shell.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SandboxPolicy {
+    /// Directory the chat works in, which commands may write to
+    pub workspace: PathBuf,
+
+    /// Other paths commands may write to
+    pub writable_paths: Vec<PathBuf>,
+
+    /// Whether commands may use the network
+    pub network: bool,
+}
+
+impl SandboxPolicy {
+    /// Make sure commands can run in the sandbox, so that a missing
+    /// requirement is reported up front instead of by every command
+    pub fn check(&self) -> Result<()> {
+        if !cfg!(target_os = "linux") {
+            bail!("The sandbox is only supported on Linux");
+        }
+
+        let output = std::process::Command::new(BWRAP)
+            .args(self.arguments(&self.workspace))
+            .arg("true")
+            .output()
+            .map_err(|e| match e.kind() {
+                ErrorKind::NotFound => {
+                    eyre!("The sandbox needs bubblewrap. Install the bwrap command, e.g. with `apt install bubblewrap`.")
+                }
+                _ => eyre!("Failed to start bwrap: {}", e),
+            })?;
+
+        if !output.status.success() {
+            bail!(
+                "The sandbox could not be set up: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+        Ok(())
+    }
+
+    /// A command that runs `program` in the sandbox, starting in `dir`
+    pub fn command(&self, program: &str, dir: &Path) -> Command {
+        let mut command = Command::new(BWRAP);
+        command.args(self.arguments(dir)).arg(program);
+        command
+    }
+
+    fn arguments(&self, dir: &Path) -> Vec<OsString> {
+        let mut arguments: Vec<OsString> = ["--ro-bind", "/", "/", "--dev", "/dev", "--proc", "/proc", "--tmpfs", "/tmp"]
+            .iter()
+            .map(OsString::from)
+            .collect();
+
+        arguments.extend(["--bind".into(), self.workspace.clone().into(), self.workspace.clone().into()]);
+        for path in &self.writable_paths {
+            arguments.extend(["--bind-try".into(), path.clone().into(), path.clone().into()]);
+        }
+
+        if !self.network {
+            arguments.push("--unshare-net".into());
+        }
+
+        // Killing the shell kills everything started in the sandbox
+        arguments.extend(["--unshare-pid", "--die-with-parent"].map(OsString::from));
+        arguments.extend(["--chdir".into(), dir.as_os_str().to_owned(), "--".into()]);
+
+        arguments
+    }
+
+    /// Whether files may be written at `path`, which is resolved through
+    /// symbolic links so that a link can't lead out of the writable paths
+    pub fn allows_write(&self, path: &Path) -> bool {
+        let path = real_path(path);
+        std::iter::once(&self.workspace)
+            .chain(&self.writable_paths)
+            .any(|dir| path.starts_with(real_path(dir)))
+    }
+
+    /// The error for a write that `allows_write` refused
+    pub fn write_error(&self, path: &Path) -> eyre::Report {
+        eyre!(
+            "The sandbox blocked writing to {}: only {} can be written. Add paths to `writable_paths` in the [sandbox] section of the global config to allow it.",
+            path.display(),
+            self.writable_dirs()
+        )
+    }
+
+    /// Explain why a failed command was probably blocked by the sandbox,
+    /// judging by its error output
+    pub fn explain(&self, stderr: &str) -> Option<String> {
+        let stderr = stderr.to_lowercase();
+
+        if stderr.contains("read-only file system") {
+            return Some(format!(
+                "The sandbox blocked writing outside of {} and /tmp. Add paths to `writable_paths` in the [sandbox] section of the global config to allow it.",
+                self.writable_dirs()
+            ));
+        }
+
+        if !self.network && NETWORK_ERRORS.iter().any(|error| stderr.contains(error)) {
+            return Some(String::from(
+                "The sandbox blocked network access. Set `network = true` in the [sandbox] section of the global config to allow it.",
+            ));
+        }
+
+        None
+    }
+
+    /// The restrictions, to tell the model about them
+    pub fn describe(&self) -> String {
+        format!(
+            "Tools run in a sandbox. They can only write to {}, shell commands also to a private /tmp, and network access is {}.",
+            self.writable_dirs(),
+            if self.network { "allowed" } else { "blocked" }
+        )
+    }
+
+    fn writable_dirs(&self) -> String {
+        std::iter::once(&self.workspace)
+            .chain(&self.writable_paths)
+            .map(|path| path.display().to_string())
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+/// `path` with symbolic links, `.` and `..` resolved as far as it exists
+fn real_path(path: &Path) -> PathBuf {
+    let mut real = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            // The part so far is already resolved, so `..` leaves the real directory
+            Component::ParentDir => {
+                real.pop();
+            }
+            component => {
+                real.push(component);
+                if let Ok(canonical) = real.canonicalize() {
+                    real = canonical;
+                }
+            }
+        }
+    }
+    real
+}
@@ -0,0 +1,676 @@
+use std::collections::VecDeque;
+use std::env;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Once, RwLock};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use eyre::{Result, eyre};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt};
+use tokio::process::{Child, ChildStderr, ChildStdin, ChildStdout, Command};
+use tokio::sync::mpsc::UnboundedSender;
+use tokio::sync::{Mutex, Notify};
+
+use super::execute_bash::{CommandEvent, CommandOutput};
+use super::sandbox::SandboxPolicy;
+use super::MAX_TOOL_RESPONSE_SIZE;
+
+/// Longest line of output sent as a `CommandEvent`; the rest of the line is
+/// only captured
+const MAX_EVENT_LINE_LENGTH: usize = 1024;
+
+/// Number of commands running, which Ctrl-C cancels instead of quitting
+static RUNNING: AtomicUsize = AtomicUsize::new(0);
+static INTERRUPT: Notify = Notify::const_new();
+static CTRL_C_LISTENER: Once = Once::new();
+
+/// Makes the marker of every command unique
+static COMMAND_COUNT: AtomicU64 = AtomicU64::new(0);
+
+/// A long-lived bash process that shell commands run in, one per chat, so
+/// that a `cd` or `export` carries over to the next command.
+///
+/// The shell starts on first use, inside the sandbox if there is one.
+/// Cloning the session shares the shell.
+#[derive(Debug, Clone)]
+pub struct ShellSession {
+    process: Arc<Mutex<Option<ShellProcess>>>,
+    sandbox: Option<SandboxPolicy>,
+    /// Working directory of the shell after the last command
+    current_dir: Arc<RwLock<PathBuf>>,
+    /// Working directory the chat started in, which a reset returns to
+    initial_dir: PathBuf,
+}
+
+#[derive(Debug)]
+struct ShellProcess {
+    child: Child,
+    stdin: ChildStdin,
+    stdout: ChildStdout,
+    stderr: ChildStderr,
+}
+
+impl ShellSession {
+    pub fn new(sandbox: Option<SandboxPolicy>) -> Self {
+        let initial_dir = env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
+        Self {
+            process: Arc::new(Mutex::new(None)),
+            sandbox,
+            current_dir: Arc::new(RwLock::new(initial_dir.clone())),
+            initial_dir,
+        }
+    }
+
+    /// Restrictions that commands run under, if any
+    pub fn sandbox(&self) -> Option<&SandboxPolicy> {
+        self.sandbox.as_ref()
+    }
+
+    /// Working directory of the shell, as of the last command
+    pub fn current_dir(&self) -> PathBuf {
+        self.current_dir.read().map(|dir| dir.clone()).unwrap_or_else(|_| self.initial_dir.clone())
+    }
+
+    /// Stop the shell, so that the next command starts a new one in the
+    /// directory the chat started in, with the original environment
+    pub async fn reset(&self) {
+        if let Some(mut process) = self.process.lock().await.take() {
+            process.kill().await;
+        }
+        if let Ok(mut dir) = self.current_dir.write() {
+            *dir = self.initial_dir.clone();
+        }
+    }
+
+    /// Run a command in the shell.
+    ///
+    /// The command runs with stdin closed, so that it can't wait for input.
+    /// When it runs longer than `timeout` or the user presses Ctrl-C the
+    /// shell is killed together with everything the command started, and
+    /// the output captured until then is part of the error. The next command
+    /// starts a new shell in the last known directory.
+    ///
+    /// When a failing command looks like the sandbox blocked it, the output
+    /// says why, and so does a `CommandEvent::Blocked`.
+    pub async fn run(
+        &self,
+        command: &str,
+        timeout: Duration,
+        events: Option<&UnboundedSender<CommandEvent>>,
+    ) -> Result<CommandOutput> {
+        let mut guard = self.process.lock().await;
+
+        // A shell that exited since the last command, e.g. killed from
+        // outside, is replaced
+        if guard.as_mut().is_some_and(|process| !matches!(process.child.try_wait(), Ok(None))) {
+            *guard = None;
+        }
+
+        let process = match guard.as_mut() {
+            Some(process) => process,
+            None => guard.insert(ShellProcess::spawn(&self.current_dir(), self.sandbox.as_ref())?),
+        };
+
+        let marker = new_marker();
+        let started = Instant::now();
+        process.stdin.write_all(script(command, &marker).as_bytes()).await?;
+        process.stdin.flush().await?;
+
+        let mut stdout = StreamCapture::new(events);
+        let mut stderr = StreamCapture::new(events);
+
+        let outcome = {
+            let _running = RunningCommand::start();
+            let run = async {
+                tokio::try_join!(
+                    read_until_marker(&mut process.stdout, &marker, &mut stdout),
+                    read_until_marker(&mut process.stderr, &marker, &mut stderr),
+                )
+            };
+
+            tokio::select! {
+                result = run => Ok(result?),
+                _ = tokio::time::sleep(timeout) => Err(format!("Command timed out after {}s and was killed", timeout.as_secs())),
+                _ = interrupted() => Err(String::from("Command was cancelled")),
+            }
+        };
+
+        let exit_code = match outcome {
+            // The trailer after the marker is the exit code and working directory
+            Ok((Some(trailer), Some(_))) => {
+                let (code, dir) = trailer.trim_start().split_once(' ').unwrap_or((trailer.trim(), ""));
+                if !dir.is_empty() {
+                    if let Ok(mut current_dir) = self.current_dir.write() {
+                        *current_dir = PathBuf::from(dir);
+                    }
+                }
+                code.parse().ok()
+            }
+            // The command ended the shell, e.g. with `exit`
+            Ok(_) => {
+                let mut process = guard.take().expect("the shell is running");
+                process.stdin.shutdown().await.ok();
+                let status = process.child.wait().await?;
+                status.code()
+            }
+            Err(reason) => {
+                if let Some(mut process) = guard.take() {
+                    process.kill().await;
+                }
+
+                // Output held back in case it was the start of the marker
+                stdout.release(stdout.pending.len());
+                stderr.release(stderr.pending.len());
+
+                let output = combine(&stdout.buffer.into_string(), &stderr.buffer.into_string());
+                if output.is_empty() {
+                    return Err(eyre!("{}. The shell session was restarted.", reason));
+                }
+                return Err(eyre!("{}. The shell session was restarted. Output so far:\n{}", reason, output));
+            }
+        };
+
+        let elapsed = started.elapsed();
+        let stderr_truncated = stderr.buffer.is_truncated();
+        let stderr = stderr.buffer.into_string();
+
+        let blocked_by_sandbox = match &self.sandbox {
+            Some(sandbox) if exit_code != Some(0) => sandbox.explain(&stderr),
+            _ => None,
+        };
+
+        if let Some(events) = events {
+            if let Some(reason) = &blocked_by_sandbox {
+                let _ = events.send(CommandEvent::Blocked(reason.clone()));
+            }
+            let _ = events.send(CommandEvent::Exited { code: exit_code, elapsed });
+        }
+
+        Ok(CommandOutput {
+            exit_code,
+            stdout_truncated: stdout.buffer.is_truncated(),
+            stderr_truncated,
+            stdout: stdout.buffer.into_string(),
+            stderr,
+            duration_secs: elapsed.as_millis() as f64 / 1000.0,
+            blocked_by_sandbox,
+        })
+    }
+}
+
+impl Default for ShellSession {
+    fn default() -> Self {
+        Self::new(None)
+    }
+}
+
+impl ShellProcess {
+    /// Start bash in its own process group, so that it can be killed
+    /// together with everything it started
+    fn spawn(dir: &Path, sandbox: Option<&SandboxPolicy>) -> Result<Self> {
+        let mut command = match sandbox {
+            Some(sandbox) => sandbox.command("bash", dir),
+            None => Command::new("bash"),
+        };
+        command
+            .arg("--noprofile")
+            .arg("--norc")
+            .current_dir(dir)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true);
+        #[cfg(unix)]
+        command.process_group(0);
+
+        let mut child = command.spawn().map_err(|e| eyre!("Failed to start the shell: {}", e))?;
+        let (Some(stdin), Some(stdout), Some(stderr)) = (child.stdin.take(), child.stdout.take(), child.stderr.take())
+        else {
+            return Err(eyre!("Failed to connect to the shell"));
+        };
+
+        Ok(Self {
+            child,
+            stdin,
+            stdout,
+            stderr,
+        })
+    }
+
+    async fn kill(&mut self) {
+        if let Some(pid) = self.child.id() {
+            kill_process_group(pid);
+        }
+        let _ = self.child.start_kill();
+        let _ = self.child.wait().await;
+    }
+}
+
+/// A marker that no command prints by accident
+fn new_marker() -> String {
+    let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map(|time| time.as_nanos()).unwrap_or_default();
+    let count = COMMAND_COUNT.fetch_add(1, Ordering::Relaxed);
+    format!("__GEMINI_CHAT_DONE_{:x}_{}__", nanos, count)
+}
+
+/// The input that runs `command` in the shell and then prints `marker` on a
+/// line of its own to both streams, followed on stdout by the exit code and
+/// working directory.
+///
+/// The command goes through `eval` so that a syntax error fails the command
+/// instead of swallowing the lines that print the marker.
+fn script(command: &str, marker: &str) -> String {
+    let quoted = command.replace('\'', r"'\''");
+    format!(
+        "eval '{quoted}' < /dev/null\n\
+         __gemini_chat_status=$?\n\
+         printf '\\n%s %d %s\\n' '{marker}' \"$__gemini_chat_status\" \"$PWD\"\n\
+         printf '\\n%s\\n' '{marker}' >&2\n"
+    )
+}
+
+/// Capture a stream up to the line break and marker printed after the
+/// command, returning the rest of the marker's line. Returns `None` if the
+/// stream ends first, i.e. the shell exited.
+///
+/// Output is captured as soon as it can't be part of the marker. If the
+/// future is dropped, what it held back is left in `capture.pending`.
+async fn read_until_marker(
+    reader: &mut (impl AsyncRead + Unpin),
+    marker: &str,
+    capture: &mut StreamCapture<'_>,
+) -> std::io::Result<Option<String>> {
+    let marker = format!("\n{}", marker).into_bytes();
+    let mut chunk = [0; 8192];
+
+    loop {
+        match find(&capture.pending, &marker) {
+            Some(start) => {
+                capture.release(start);
+                let trailer = &capture.pending[marker.len()..];
+                if let Some(end) = trailer.iter().position(|&byte| byte == b'\n') {
+                    let trailer = String::from_utf8_lossy(&trailer[..end]).into_owned();
+                    capture.pending.clear();
+                    capture.finish();
+                    return Ok(Some(trailer));
+                }
+            }
+            None => {
+                // Hold back only what could be the start of the marker
+                let keep = partial_match(&capture.pending, &marker);
+                capture.release(capture.pending.len() - keep);
+                if keep > 0 {
+                    // The held back line break ends the line either way
+                    capture.end_line();
+                }
+            }
+        }
+
+        let n = reader.read(&mut chunk).await?;
+        if n == 0 {
+            capture.release(capture.pending.len());
+            capture.finish();
+            return Ok(None);
+        }
+        capture.pending.extend_from_slice(&chunk[..n]);
+    }
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|window| window == needle)
+}
+
+/// Length of the longest end of `haystack` that `needle` starts with, short
+/// of all of `needle`
+fn partial_match(haystack: &[u8], needle: &[u8]) -> usize {
+    (1..needle.len().min(haystack.len() + 1))
+        .rev()
+        .find(|&len| haystack.ends_with(&needle[..len]))
+        .unwrap_or(0)
+}
+
+/// Join stdout and stderr, each starting on a line of its own
+pub fn combine(stdout: &str, stderr: &str) -> String {
+    let mut result = stdout.to_string();
+    if !result.is_empty() && !stderr.is_empty() && !result.ends_with('\n') {
+        result.push('\n');
+    }
+    result.push_str(stderr);
+    result
+}
+
+/// Output of one stream of a command as it is read: kept in a bounded
+/// buffer and sent to `events` a line at a time
+struct StreamCapture<'a> {
+    buffer: OutputBuffer,
+    events: Option<&'a UnboundedSender<CommandEvent>>,
+    line: Vec<u8>,
+    /// Whether `line` was sent before its line break arrived
+    line_sent: bool,
+    /// Bytes read that may be the start of the marker
+    pending: Vec<u8>,
+}
+
+impl<'a> StreamCapture<'a> {
+    fn new(events: Option<&'a UnboundedSender<CommandEvent>>) -> Self {
+        Self {
+            buffer: OutputBuffer::new(MAX_TOOL_RESPONSE_SIZE / 2),
+            events,
+            line: Vec::new(),
+            line_sent: false,
+            pending: Vec::new(),
+        }
+    }
+
+    /// Capture the first `len` bytes of `pending`
+    fn release(&mut self, len: usize) {
+        let bytes: Vec<u8> = self.pending.drain(..len).collect();
+        self.write(&bytes);
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        self.buffer.push(bytes);
+
+        if self.events.is_none() {
+            return;
+        }
+        for &byte in bytes {
+            if byte == b'\n' {
+                if !std::mem::take(&mut self.line_sent) {
+                    self.send_line();
+                }
+            } else if self.line.len() < MAX_EVENT_LINE_LENGTH {
+                self.line_sent = false;
+                self.line.push(byte);
+            }
+        }
+    }
+
+    /// Send the line so far, as its line break is held back
+    fn end_line(&mut self) {
+        if !self.line.is_empty() {
+            self.send_line();
+            self.line_sent = true;
+        }
+    }
+
+    /// Send the last line if it didn't end with a line break
+    fn finish(&mut self) {
+        if !self.line.is_empty() {
+            self.send_line();
+        }
+    }
+
+    fn send_line(&mut self) {
+        if let Some(events) = self.events {
+            let _ = events.send(CommandEvent::Line(String::from_utf8_lossy(&self.line).into_owned()));
+        }
+        self.line.clear();
+    }
+}
+
+/// Output of one stream of a command. Past `limit` bytes only the first and
+/// the last half of the limit are kept, as that is where commands usually
+/// print what they are doing and how it ended.
+struct OutputBuffer {
+    head: Vec<u8>,
+    tail: VecDeque<u8>,
+    omitted: usize,
+    limit: usize,
+}
+
+impl OutputBuffer {
+    fn new(limit: usize) -> Self {
+        Self {
+            head: Vec::new(),
+            tail: VecDeque::new(),
+            omitted: 0,
+            limit,
+        }
+    }
+
+    fn push(&mut self, bytes: &[u8]) {
+        let head_limit = self.limit / 2;
+        let to_head = head_limit.saturating_sub(self.head.len()).min(bytes.len());
+        self.head.extend_from_slice(&bytes[..to_head]);
+        self.tail.extend(&bytes[to_head..]);
+
+        let excess = self.tail.len().saturating_sub(self.limit - head_limit);
+        self.tail.drain(..excess);
+        self.omitted += excess;
+    }
+
+    fn is_truncated(&self) -> bool {
+        self.omitted > 0
+    }
+
+    fn into_string(self) -> String {
+        let head = String::from_utf8_lossy(&self.head);
+        let tail: Vec<u8> = self.tail.into_iter().collect();
+        let tail = String::from_utf8_lossy(&tail);
+
+        if self.omitted == 0 {
+            return format!("{}{}", head, tail);
+        }
+        format!("{}\n[... {} bytes omitted ...]\n{}", head, self.omitted, tail)
+    }
+}
+
+/// Marks a command as running for as long as it is alive, so that Ctrl-C
+/// cancels it, see `interrupted`
+pub struct RunningCommand;
+
+impl RunningCommand {
+    pub fn start() -> Self {
+        RUNNING.fetch_add(1, Ordering::SeqCst);
+        Self
+    }
+}
+
+impl Drop for RunningCommand {
+    fn drop(&mut self) {
+        RUNNING.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Resolves when the user presses Ctrl-C.
+///
+/// A signal handler can't be removed once installed, so Ctrl-C would no
+/// longer quit the application. Without a running command it still does.
+pub async fn interrupted() {
+    CTRL_C_LISTENER.call_once(|| {
+        tokio::spawn(async {
+            while tokio::signal::ctrl_c().await.is_ok() {
+                if RUNNING.load(Ordering::SeqCst) == 0 {
+                    std::process::exit(130);
+                }
+                INTERRUPT.notify_waiters();
+            }
+        });
+    });
+    INTERRUPT.notified().await
+}
+
+/// Kill a process and everything it started
+#[cfg(unix)]
+pub fn kill_process_group(pid: u32) {
+    // SAFETY: killpg has no memory safety requirements
+    unsafe {
+        libc::killpg(pid as libc::pid_t, libc::SIGKILL);
+    }
+}
+
+#[cfg(not(unix))]
+pub fn kill_process_group(_pid: u32) {}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+    use std::pin::Pin;
+    use std::task::{Context, Poll};
+
+    use tokio::io::ReadBuf;
+    use tokio::sync::mpsc;
+
+    use super::*;
+
+    const MARKER: &str = "__MARKER__";
+
+    /// A stream that returns the given chunks, one per read
+    struct Chunks(VecDeque<&'static [u8]>);
+
+    impl AsyncRead for Chunks {
+        fn poll_read(mut self: Pin<&mut Self>, _cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<std::io::Result<()>> {
+            if let Some(chunk) = self.0.pop_front() {
+                buf.put_slice(chunk);
+            }
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    async fn read_chunks(chunks: &[&'static [u8]]) -> (Option<String>, String) {
+        let mut reader = Chunks(chunks.iter().copied().collect());
+        let mut capture = StreamCapture::new(None);
+        let trailer = read_until_marker(&mut reader, MARKER, &mut capture).await.unwrap();
+        (trailer, capture.buffer.into_string())
+    }
+
+    fn lines(events: &mut mpsc::UnboundedReceiver<CommandEvent>) -> Vec<String> {
+        let mut lines = Vec::new();
+        while let Ok(event) = events.try_recv() {
+            if let CommandEvent::Line(line) = event {
+                lines.push(line);
+            }
+        }
+        lines
+    }
+
+    #[tokio::test]
+    async fn marker_split_across_reads() {
+        let (trailer, output) = read_chunks(&[b"one\ntwo\n\n__MAR", b"KER", b"__ 0 /tmp", b"\n"]).await;
+        assert_eq!(trailer.as_deref(), Some(" 0 /tmp"));
+        assert_eq!(output, "one\ntwo\n");
+    }
+
+    #[tokio::test]
+    async fn output_without_trailing_newline() {
+        let (trailer, output) = read_chunks(&[b"no newline", b"\n__MARKER__ 1 /\n"]).await;
+        assert_eq!(trailer.as_deref(), Some(" 1 /"));
+        assert_eq!(output, "no newline");
+    }
+
+    #[tokio::test]
+    async fn stream_ending_before_the_marker() {
+        let (trailer, output) = read_chunks(&[b"bye\n\n__MARK"]).await;
+        assert_eq!(trailer, None);
+        assert_eq!(output, "bye\n\n__MARK");
+    }
+
+    #[tokio::test]
+    async fn lines_are_sent_before_the_marker_arrives() {
+        let (mut writer, mut reader) = tokio::io::duplex(64);
+        let (sender, mut events) = mpsc::unbounded_channel();
+        let mut capture = StreamCapture::new(Some(&sender));
+
+        writer.write_all(b"first-line\nsecond").await.unwrap();
+        let read = tokio::time::timeout(Duration::from_millis(50), read_until_marker(&mut reader, MARKER, &mut capture));
+        assert!(read.await.is_err());
+        assert_eq!(lines(&mut events), vec!["first-line"]);
+
+        writer.write_all(b"-line\n").await.unwrap();
+        let read = tokio::time::timeout(Duration::from_millis(50), read_until_marker(&mut reader, MARKER, &mut capture));
+        assert!(read.await.is_err());
+        assert_eq!(lines(&mut events), vec!["second-line"]);
+
+        // Only the line break was held back, in case the marker followed
+        assert_eq!(capture.pending, b"\n");
+        writer.write_all(b"\n__MARKER__ 0 /\n").await.unwrap();
+        let trailer = read_until_marker(&mut reader, MARKER, &mut capture).await.unwrap();
+        assert_eq!(trailer.as_deref(), Some(" 0 /"));
+        assert_eq!(lines(&mut events), Vec::<String>::new());
+        assert_eq!(capture.buffer.into_string(), "first-line\nsecond-line\n");
+    }
+
+    #[test]
+    fn script_quotes_single_quotes() {
+        let script = script("echo 'a b'", "M");
+        assert!(script.starts_with(r"eval 'echo '\''a b'\''' < /dev/null"), "{}", script);
+    }
+
+    #[tokio::test]
+    async fn commands_with_quotes_run_as_written() {
+        let shell = ShellSession::default();
+        let output = shell.run("echo \"it's\" 'a \"test\"'", Duration::from_secs(10), None).await.unwrap();
+        assert_eq!(output.stdout, "it's a \"test\"\n");
+    }
+
+    #[tokio::test]
+    async fn directory_and_environment_persist() {
+        let shell = ShellSession::default();
+        let dir = env::temp_dir().canonicalize().unwrap();
+        let timeout = Duration::from_secs(10);
+
+        shell.run(&format!("cd '{}' && export GEMINI_CHAT_TEST=kept", dir.display()), timeout, None).await.unwrap();
+        let output = shell.run("echo \"$GEMINI_CHAT_TEST $PWD\"", timeout, None).await.unwrap();
+
+        assert_eq!(output.stdout, format!("kept {}\n", dir.display()));
+        assert_eq!(shell.current_dir(), dir);
+    }
+
+    #[tokio::test]
+    async fn exit_restarts_the_shell_in_the_last_directory() {
+        let shell = ShellSession::default();
+        let dir = env::temp_dir().canonicalize().unwrap();
+        let timeout = Duration::from_secs(10);
+
+        shell.run(&format!("cd '{}' && export GEMINI_CHAT_TEST=lost", dir.display()), timeout, None).await.unwrap();
+        let output = shell.run("exit 3", timeout, None).await.unwrap();
+        assert_eq!(output.exit_code, Some(3));
+
+        let output = shell.run("echo \"${GEMINI_CHAT_TEST:-unset} $PWD\"", timeout, None).await.unwrap();
+        assert_eq!(output.stdout, format!("unset {}\n", dir.display()));
+    }
+
+    #[tokio::test]
+    async fn timeout_keeps_the_output_so_far() {
+        let shell = ShellSession::default();
+        let error = shell.run("echo started; sleep 5", Duration::from_millis(500), None).await.unwrap_err();
+
+        let error = error.to_string();
+        assert!(error.starts_with("Command timed out"), "{}", error);
+        assert!(error.ends_with("Output so far:\nstarted\n"), "{}", error);
+    }
+
+    #[test]
+    fn output_buffer_keeps_head_and_tail() {
+        let mut buffer = OutputBuffer::new(10);
+        buffer.push(b"abc");
+        buffer.push(b"defghijklmnop");
+        buffer.push(b"qrst");
+
+        assert!(buffer.is_truncated());
+        assert_eq!(buffer.into_string(), "abcde\n[... 10 bytes omitted ...]\npqrst");
+    }
+
+    #[test]
+    fn output_buffer_within_limit_is_unchanged() {
+        let mut buffer = OutputBuffer::new(10);
+        buffer.push(b"0123456789");
+
+        assert!(!buffer.is_truncated());
+        assert_eq!(buffer.into_string(), "0123456789");
+    }
+
+    #[tokio::test]
+    async fn large_output_is_capped_at_the_response_size() {
+        let shell = ShellSession::default();
+        let command = format!("head -c {} /dev/zero | tr '\\0' x; echo end", MAX_TOOL_RESPONSE_SIZE);
+        let output = shell.run(&command, Duration::from_secs(30), None).await.unwrap();
+
+        assert!(output.stdout_truncated);
+        assert!(output.stdout.len() < MAX_TOOL_RESPONSE_SIZE / 2 + 100);
+        assert!(output.stdout.starts_with("xxx"));
+        assert!(output.stdout.ends_with("xxend\n"));
+    }
+}
@@ -1,6 +1,133 @@
+use std::process::Stdio;
+
+use async_trait::async_trait;
 use eyre::{Result, eyre};
-use std::process::Command;
+use serde::Deserialize;
+use tokio::process::Command;
+
+use super::shell_session::{interrupted, kill_process_group, RunningCommand};
+use super::{Tool, ToolContext, ToolOutput};
+
+/// Operation name prefixes that only read state and can run without approval
+const READ_ONLY_PREFIXES: &[&str] = &["describe-", "list-", "get-"];
+
+/// Arguments of the `use_aws` tool
+#[derive(Debug, Clone, Deserialize)]
+pub struct UseAws {
+    pub service_name: String,
+    pub operation_name: String,
+    pub region: String,
+    /// Either a JSON object or a JSON-encoded string, since some providers
+    /// cannot declare free-form object parameters
+    #[serde(default)]
+    pub parameters: Option<serde_json::Value>,
+    pub profile_name: Option<String>,
+    pub label: String,
+}
+
+impl UseAws {
+    /// Whether the operation only reads state, judged by its name.
+    /// Operation names are accepted in kebab case or in the API's
+    /// PascalCase form (`DescribeInstances`).
+    pub fn is_read_only(&self) -> bool {
+        let operation = to_kebab_case(self.operation_name.trim());
+        READ_ONLY_PREFIXES.iter().any(|prefix| operation.starts_with(prefix))
+    }
+    
+    fn parameters_json(&self) -> Result<String> {
+        match &self.parameters {
+            None | Some(serde_json::Value::Null) => Ok(String::new()),
+            Some(serde_json::Value::Object(map)) => Ok(serde_json::to_string(map)?),
+            Some(serde_json::Value::String(s)) if s.trim().is_empty() => Ok(String::new()),
+            Some(serde_json::Value::String(s)) => match serde_json::from_str::<serde_json::Value>(s) {
+                Ok(serde_json::Value::Object(_)) => Ok(s.clone()),
+                _ => Err(eyre!("parameters must be a JSON object")),
+            },
+            Some(_) => Err(eyre!("parameters must be a JSON object")),
+        }
+    }
+}
+
+#[async_trait]
+impl Tool for UseAws {
+    fn validate(&self) -> Result<()> {
+        if self.service_name.trim().is_empty() || self.operation_name.trim().is_empty() {
+            return Err(eyre!("Service and operation names cannot be empty"));
+        }
+        self.parameters_json()?;
+        Ok(())
+    }
+    
+    async fn execute(&self, context: &ToolContext) -> Result<ToolOutput> {
+        let parameters = self.parameters_json()?;
+        
+        let output = use_aws(
+            &self.service_name,
+            &self.operation_name,
+            &self.region,
+            &parameters,
+            self.profile_name.as_deref(),
+            &self.label,
+            context,
+        ).await?;
+        
+        Ok(ToolOutput::text(output))
+    }
+    
+    fn describe(&self) -> String {
+        let mut description = format!(
+            "{}\n\nI will run the following AWS CLI operation{}:\n\n  aws {} {} --region {}",
+            self.label,
+            if self.is_read_only() { "" } else { ", which may modify resources" },
+            self.service_name,
+            self.operation_name,
+            self.region
+        );
+        
+        if let Some(profile) = &self.profile_name {
+            description.push_str(&format!(" --profile {}", profile));
+        }
+        
+        if let Ok(parameters) = self.parameters_json() {
+            if !parameters.is_empty() {
+                description.push_str(&format!("\n\n  Parameters: {}", parameters));
+            }
+        }
+        
+        description.push('\n');
+        description
+    }
+    
+    fn requires_approval(&self) -> bool {
+        !self.is_read_only()
+    }
+    
+    fn requires_explicit_approval(&self) -> bool {
+        !self.is_read_only()
+    }
+}
+
+fn to_kebab_case(name: &str) -> String {
+    let mut result = String::new();
+    for (i, c) in name.chars().enumerate() {
+        if c.is_uppercase() {
+            if i > 0 && !result.ends_with('-') {
+                result.push('-');
+            }
+            result.extend(c.to_lowercase());
+        } else {
+            result.push(c);
+        }
+    }
+    result
+}
 
+/// Run an AWS CLI operation and return its output.
+///
+/// The CLI runs with stdin closed, so that a credential prompt fails instead
+/// of waiting, and in the sandbox if there is one. It is killed together
+/// with everything it started when it runs longer than the context's
+/// `bash_timeout` or the user presses Ctrl-C.
 pub async fn use_aws(
     service_name: &str,
     operation_name: &str,
@@ -8,8 +135,20 @@ pub async fn use_aws(
     parameters: &str,
     profile_name: Option<&str>,
     label: &str,
+    context: &ToolContext,
 ) -> Result<String> {
-    let mut cmd = Command::new("aws");
+    tracing::debug!("Calling AWS CLI: {} ({} {})", label, service_name, operation_name);
+    
+    let timeout = context.bash_timeout;
+    let mut cmd = match context.shell.sandbox() {
+        Some(sandbox) if !sandbox.network => {
+            return Err(eyre!(
+                "The sandbox blocked the AWS CLI, which needs network access. Set `network = true` in the [sandbox] section of the global config to allow it."
+            ));
+        }
+        Some(sandbox) => sandbox.command("aws", &context.shell.current_dir()),
+        None => Command::new("aws"),
+    };
     
     cmd.arg(service_name)
         .arg(operation_name)
@@ -57,7 +196,33 @@ pub async fn use_aws(
     }
     
     // Execute the command
-    let output = cmd.output()?;
+    cmd.stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true);
+    #[cfg(unix)]
+    cmd.process_group(0);
+    
+    let child = cmd.spawn().map_err(|e| eyre!("Failed to run the AWS CLI: {}", e))?;
+    let pid = child.id();
+    let outcome = {
+        let _running = RunningCommand::start();
+        tokio::select! {
+            output = child.wait_with_output() => Ok(output?),
+            _ = tokio::time::sleep(timeout) => Err(format!("AWS CLI timed out after {}s and was killed", timeout.as_secs())),
+            _ = interrupted() => Err(String::from("AWS CLI call was cancelled")),
+        }
+    };
+
+    let output = match outcome {
+        Ok(output) => output,
+        Err(reason) => {
+            if let Some(pid) = pid {
+                kill_process_group(pid);
+            }
+            return Err(eyre!(reason));
+        }
+    };
     
     if output.status.success() {
         let stdout = String::from_utf8_lossy(&output.stdout).to_string();
@@ -67,3 +232,124 @@ pub async fn use_aws(
         Err(eyre!("AWS CLI error: {}", stderr))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+    use std::path::PathBuf;
+    use std::sync::Once;
+    use std::time::Duration;
+
+    use serde_json::json;
+
+    use super::*;
+    use crate::cli::chat::tools::shell_session::ShellSession;
+
+    static FAKE_AWS: Once = Once::new();
+
+    /// Put a fake `aws` on `PATH` that prints its arguments one per line,
+    /// or for the `hang` operation records the PID of a child and waits
+    fn fake_aws_dir() -> PathBuf {
+        let dir = env::temp_dir().join("gemini-chat-fake-aws");
+        FAKE_AWS.call_once(|| {
+            fs::create_dir_all(&dir).unwrap();
+            let script = dir.join("aws");
+            fs::write(
+                &script,
+                "#!/bin/sh\n\
+                 if [ \"$2\" = hang ]; then\n\
+                 sleep 30 &\n\
+                 echo $! > \"$(dirname \"$0\")/child.pid\"\n\
+                 wait\n\
+                 fi\n\
+                 printf '%s\\n' \"$@\"\n",
+            )
+            .unwrap();
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::PermissionsExt;
+                fs::set_permissions(&script, fs::Permissions::from_mode(0o755)).unwrap();
+            }
+            let path = env::var("PATH").unwrap_or_default();
+            env::set_var("PATH", format!("{}:{}", dir.display(), path));
+        });
+        dir
+    }
+
+    fn context(timeout: Duration) -> ToolContext {
+        ToolContext {
+            bash_timeout: timeout,
+            events: None,
+            shell: ShellSession::default(),
+        }
+    }
+
+    fn tool(operation: &str, parameters: Option<serde_json::Value>) -> UseAws {
+        UseAws {
+            service_name: String::from("ec2"),
+            operation_name: operation.to_string(),
+            region: String::from("us-east-1"),
+            parameters,
+            profile_name: Some(String::from("dev")),
+            label: String::from("Test"),
+        }
+    }
+
+    async fn arguments(tool: UseAws) -> Vec<String> {
+        fake_aws_dir();
+        let output = tool.execute(&context(Duration::from_secs(10))).await.unwrap();
+        output.into_response()["output"].as_str().unwrap().lines().map(String::from).collect()
+    }
+
+    #[tokio::test]
+    async fn object_parameters_become_flags() {
+        let parameters = json!({ "cli-input-json": { "InstanceIds": ["i-1"] }, "dry_run": "" });
+        let arguments = arguments(tool("describe-instances", Some(parameters))).await;
+
+        assert_eq!(
+            arguments,
+            [
+                "ec2", "describe-instances", "--region", "us-east-1", "--profile", "dev",
+                "--cli-input-json", r#"{"InstanceIds":["i-1"]}"#, "--dry-run",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn string_parameters_are_parsed_as_json() {
+        let parameters = json!(r#"{"cli-input-json": {"InstanceIds": ["i-1"]}, "max-items": 5}"#);
+        let arguments = arguments(tool("describe-instances", Some(parameters))).await;
+
+        assert_eq!(&arguments[6..], ["--cli-input-json", r#"{"InstanceIds":["i-1"]}"#, "--max-items", "5"]);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn timeout_kills_the_cli_and_its_children() {
+        let dir = fake_aws_dir();
+        let error = tool("hang", None).execute(&context(Duration::from_millis(500))).await.unwrap_err();
+        assert!(error.to_string().contains("timed out"), "{}", error);
+
+        let pid = fs::read_to_string(dir.join("child.pid")).unwrap();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let stat = fs::read_to_string(format!("/proc/{}/stat", pid.trim())).unwrap_or_default();
+        // A killed child that nobody reaped yet is a zombie
+        assert!(stat.is_empty() || stat.contains(") Z "), "{}", stat);
+    }
+
+    #[test]
+    fn read_only_operations() {
+        assert!(tool("DescribeInstances", None).is_read_only());
+        assert!(tool("get-object", None).is_read_only());
+        assert!(tool("list-buckets", None).is_read_only());
+        assert!(!tool("TerminateInstances", None).is_read_only());
+        assert!(!tool("put-object", None).is_read_only());
+    }
+
+    #[test]
+    fn kebab_case() {
+        assert_eq!(to_kebab_case("DescribeInstances"), "describe-instances");
+        assert_eq!(to_kebab_case("get-object"), "get-object");
+    }
+}
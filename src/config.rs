@@ -0,0 +1,297 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use eyre::{Result, eyre};
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::cli::chat::tools::execute_bash;
+use crate::cli::chat::tools::sandbox::SandboxPolicy;
+use crate::cli::chat::tools::sanitize_path;
+use crate::model_provider::{GenerationConfig, ProviderKind};
+use crate::retry::RetryPolicy;
+
+/// Name of the per-project configuration file, looked up from the current
+/// directory upwards
+pub const PROJECT_CONFIG_FILE: &str = ".gemini-chat.toml";
+
+/// Default limit on tool call rounds before the agent loop gives up
+pub const DEFAULT_MAX_STEPS: usize = 25;
+
+/// Settings read from configuration files and the command line.
+///
+/// Every field is optional so that layers can be merged: the project file
+/// overrides the global file and command line flags override both. Unset
+/// fields fall back to the defaults of the accessor methods or the provider.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub provider: Option<ProviderKind>,
+    pub model: Option<String>,
+    /// API base URL of the provider, overriding its environment variable.
+    /// Not read from project files.
+    pub base_url: Option<String>,
+    pub max_steps: Option<usize>,
+    pub context_budget: Option<usize>,
+    pub generation: GenerationConfig,
+    pub tools: ToolSettings,
+    pub approval: ApprovalSettings,
+    pub prompt: PromptSettings,
+    pub network: NetworkSettings,
+    pub history: HistorySettings,
+    pub sandbox: SandboxSettings,
+
+    /// Files the configuration was loaded from, in the order applied
+    #[serde(skip)]
+    pub sources: Vec<PathBuf>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ToolSettings {
+    /// Tools advertised to the model; all built-in tools when unset
+    pub enabled: Option<Vec<String>>,
+
+    /// Seconds a shell command or AWS CLI call may run before it is killed
+    pub bash_timeout_secs: Option<u64>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ApprovalSettings {
+    /// Run tools without asking, like `--yes`
+    pub accept_all: Option<bool>,
+
+    /// Tools that never need approval
+    pub trusted_tools: Option<Vec<String>>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PromptSettings {
+    /// Extra instructions appended to the system prompt
+    pub additions: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct NetworkSettings {
+    /// Attempts made for a failed API request, including the first one
+    pub max_attempts: Option<u32>,
+
+    /// Seconds allowed for a complete API request, including the streamed response
+    pub timeout_secs: Option<u64>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HistorySettings {
+    /// Number of input lines remembered across sessions
+    pub max_size: Option<usize>,
+
+    /// Keep a separate history for every project
+    pub per_project: Option<bool>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SandboxSettings {
+    /// Run tools in a sandbox, like `--sandbox`
+    pub enabled: Option<bool>,
+
+    /// Allow tools in the sandbox to use the network. Not read from
+    /// project files.
+    pub network: Option<bool>,
+
+    /// Paths tools in the sandbox may write to besides the workspace and
+    /// `/tmp`, e.g. `~/.cargo`. Not read from project files.
+    pub writable_paths: Option<Vec<String>>,
+}
+
+impl Config {
+    /// Load the global configuration file and the nearest project file.
+    ///
+    /// A project file comes with the code being worked on, e.g. a cloned
+    /// repository, so the settings that protect the user are ignored there,
+    /// see `remove_trusted_settings`.
+    pub fn load() -> Result<Self> {
+        let mut config = Config::default();
+
+        if let Some(path) = global_path().filter(|path| path.is_file()) {
+            config.merge(Config::from_file(&path)?);
+        }
+
+        if let Some(path) = project_path() {
+            let mut project = Config::from_file(&path)?;
+            for key in project.remove_trusted_settings() {
+                warn!(
+                    "Ignoring {} in {}: it is only read from the global config file",
+                    key,
+                    path.display()
+                );
+            }
+            config.merge(project);
+        }
+
+        Ok(config)
+    }
+
+    /// Parse a single configuration file
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let content = fs::read_to_string(path)
+            .map_err(|e| eyre!("Failed to read config file {}: {}", path.display(), e))?;
+
+        let mut config: Config = toml::from_str(&content)
+            .map_err(|e| eyre!("Invalid config file {}: {}", path.display(), e))?;
+        config.sources = vec![path.to_path_buf()];
+
+        Ok(config)
+    }
+
+    /// Unset the settings that only the user may change, returning the keys
+    /// of those that were set. These are the settings that skip approval
+    /// prompts, the API URL, which the API key is sent to, and those that
+    /// loosen the sandbox, including turning it off. A project can still
+    /// turn the sandbox on.
+    pub fn remove_trusted_settings(&mut self) -> Vec<&'static str> {
+        let mut removed = Vec::new();
+        if self.base_url.take().is_some() {
+            removed.push("base_url");
+        }
+        if self.approval.accept_all.take().is_some() {
+            removed.push("approval.accept_all");
+        }
+        if self.approval.trusted_tools.take().is_some() {
+            removed.push("approval.trusted_tools");
+        }
+        if self.sandbox.enabled == Some(false) {
+            self.sandbox.enabled = None;
+            removed.push("sandbox.enabled = false");
+        }
+        if self.sandbox.network.take().is_some() {
+            removed.push("sandbox.network");
+        }
+        if self.sandbox.writable_paths.take().is_some() {
+            removed.push("sandbox.writable_paths");
+        }
+        removed
+    }
+
+    /// Apply the values set in `other` on top of this configuration
+    pub fn merge(&mut self, other: Config) {
+        fn set<T>(target: &mut Option<T>, value: Option<T>) {
+            if value.is_some() {
+                *target = value;
+            }
+        }
+
+        set(&mut self.provider, other.provider);
+        set(&mut self.model, other.model);
+        set(&mut self.base_url, other.base_url);
+        set(&mut self.max_steps, other.max_steps);
+        set(&mut self.context_budget, other.context_budget);
+        set(&mut self.generation.temperature, other.generation.temperature);
+        set(&mut self.generation.top_p, other.generation.top_p);
+        set(&mut self.generation.top_k, other.generation.top_k);
+        set(&mut self.generation.max_output_tokens, other.generation.max_output_tokens);
+        set(&mut self.tools.enabled, other.tools.enabled);
+        set(&mut self.tools.bash_timeout_secs, other.tools.bash_timeout_secs);
+        set(&mut self.approval.accept_all, other.approval.accept_all);
+        set(&mut self.approval.trusted_tools, other.approval.trusted_tools);
+        set(&mut self.prompt.additions, other.prompt.additions);
+        set(&mut self.network.max_attempts, other.network.max_attempts);
+        set(&mut self.network.timeout_secs, other.network.timeout_secs);
+        set(&mut self.history.max_size, other.history.max_size);
+        set(&mut self.history.per_project, other.history.per_project);
+        set(&mut self.sandbox.enabled, other.sandbox.enabled);
+        set(&mut self.sandbox.network, other.sandbox.network);
+        set(&mut self.sandbox.writable_paths, other.sandbox.writable_paths);
+        self.sources.extend(other.sources);
+    }
+
+    pub fn provider(&self) -> ProviderKind {
+        self.provider.unwrap_or(ProviderKind::Gemini)
+    }
+
+    pub fn max_steps(&self) -> usize {
+        self.max_steps.unwrap_or(DEFAULT_MAX_STEPS)
+    }
+
+    pub fn accept_all(&self) -> bool {
+        self.approval.accept_all.unwrap_or(false)
+    }
+
+    pub fn bash_timeout(&self) -> Duration {
+        self.tools.bash_timeout_secs.map(Duration::from_secs).unwrap_or(execute_bash::DEFAULT_TIMEOUT)
+    }
+
+    /// Restrictions for shell commands, or `None` when the sandbox is off.
+    /// The current directory is the workspace.
+    pub fn sandbox_policy(&self) -> Result<Option<SandboxPolicy>> {
+        if !self.sandbox.enabled.unwrap_or(false) {
+            return Ok(None);
+        }
+
+        let workspace = env::current_dir()?;
+        Ok(Some(SandboxPolicy {
+            writable_paths: self
+                .sandbox
+                .writable_paths
+                .iter()
+                .flatten()
+                .map(|path| sanitize_path(path, &workspace))
+                .collect(),
+            workspace,
+            network: self.sandbox.network.unwrap_or(false),
+        }))
+    }
+
+    pub fn retry_policy(&self) -> RetryPolicy {
+        let default = RetryPolicy::default();
+        RetryPolicy {
+            max_attempts: self.network.max_attempts.unwrap_or(default.max_attempts),
+            timeout: self.network.timeout_secs.map(Duration::from_secs).unwrap_or(default.timeout),
+        }
+    }
+}
+
+/// Path of the global configuration file, e.g. `~/.config/gemini-chat-cli/config.toml`
+pub fn global_path() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("gemini-chat-cli").join("config.toml"))
+}
+
+/// Find the nearest project configuration file in the current directory or
+/// one of its parents
+pub fn project_path() -> Option<PathBuf> {
+    let current_dir = env::current_dir().ok()?;
+    current_dir
+        .ancestors()
+        .map(|dir| dir.join(PROJECT_CONFIG_FILE))
+        .find(|path| path.is_file())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn project_files_cannot_loosen_the_sandbox() {
+        let mut global: Config = toml::from_str("[sandbox]\nenabled = true").unwrap();
+        let mut project: Config = toml::from_str("[sandbox]\nenabled = false\nnetwork = true").unwrap();
+
+        assert_eq!(project.remove_trusted_settings(), vec!["sandbox.enabled = false", "sandbox.network"]);
+        global.merge(project);
+        assert_eq!(global.sandbox.enabled, Some(true));
+        assert_eq!(global.sandbox.network, None);
+    }
+
+    #[test]
+    fn project_files_can_enable_the_sandbox() {
+        let mut project: Config = toml::from_str("[sandbox]\nenabled = true").unwrap();
+
+        assert!(project.remove_trusted_settings().is_empty());
+        assert_eq!(project.sandbox.enabled, Some(true));
+    }
+}
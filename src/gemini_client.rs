@@ -1,29 +1,117 @@
 use std::env;
 
+use async_trait::async_trait;
 use eyre::{Result, eyre};
+use futures::stream::{self, BoxStream, StreamExt};
+use serde::Deserialize;
 use serde_json::{json, Value};
-use tracing::{error, debug, info};
+use tokio::sync::mpsc::UnboundedSender;
+use tracing::{error, debug};
 
-pub struct ToolDefinition {
-    pub name: String,
-    pub description: String,
-    pub parameters: Value,
+use crate::model_provider::{
+    Content,
+    FunctionCall,
+    GenerationConfig,
+    ModelError,
+    ModelProvider,
+    ModelResponse,
+    Role,
+    ToolDefinition,
+};
+use crate::redact;
+use crate::retry::{self, RetryNotice, RetryPolicy};
+use crate::sse::SseDecoder;
+
+const DEFAULT_BASE_URL: &str = "https://generativelanguage.googleapis.com/v1beta";
+const DEFAULT_MODEL: &str = "gemini-2.0-flash";
+
+/// Input token limit of the Gemini 2.0 models
+const CONTEXT_WINDOW: usize = 1_048_576;
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GenerateContentResponse {
+    #[serde(default)]
+    candidates: Vec<Candidate>,
+    prompt_feedback: Option<PromptFeedback>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PromptFeedback {
+    block_reason: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Candidate {
+    content: Option<CandidateContent>,
+    finish_reason: Option<String>,
+    finish_message: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CountTokensResponse {
+    total_tokens: usize,
+}
+
+#[derive(Debug, Deserialize)]
+struct CandidateContent {
+    #[serde(default)]
+    parts: Vec<ResponsePart>,
 }
 
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ResponsePart {
+    text: Option<String>,
+    function_call: Option<FunctionCall>,
+}
+
+/// Client for the Gemini API.
+///
+/// The API key from `GEMINI_API_KEY` is sent in the `x-goog-api-key` header
+/// rather than the URL, so it can't leak through logged URLs. The base URL
+/// can be overridden in the global configuration or with `GEMINI_BASE_URL`,
+/// which is not read from `.env` files, e.g. to go through a proxy or to use
+/// a local mock server.
 pub struct GeminiClient {
     api_key: String,
+    base_url: String,
+    model: String,
+    generation: GenerationConfig,
+    retry: RetryPolicy,
+    retry_notifier: Option<UnboundedSender<RetryNotice>>,
     client: reqwest::Client,
 }
 
 impl GeminiClient {
-    pub fn new() -> Result<Self> {
+    pub fn new(
+        model: Option<String>,
+        base_url: Option<String>,
+        generation: GenerationConfig,
+        retry: RetryPolicy,
+    ) -> Result<Self> {
         let api_key = env::var("GEMINI_API_KEY")
             .map_err(|_| eyre!("GEMINI_API_KEY environment variable not set"))?;
+        redact::register_secret(&api_key);
+        
+        let base_url = base_url
+            .or_else(|| env::var("GEMINI_BASE_URL").ok())
+            .unwrap_or_else(|| DEFAULT_BASE_URL.to_string());
         
-        let client = reqwest::Client::new();
+        let client = reqwest::Client::builder()
+            .timeout(retry.timeout)
+            .build()?;
         
         Ok(Self {
             api_key,
+            base_url: base_url.trim_end_matches('/').to_string(),
+            model: model.unwrap_or_else(|| DEFAULT_MODEL.to_string()),
+            generation,
+            retry,
+            retry_notifier: None,
             client,
         })
     }
@@ -31,157 +119,499 @@ impl GeminiClient {
     pub async fn generate_content(
         &self,
         system_prompt: &str,
-        messages: &[(&str, &str)],
+        messages: &[Content],
         tools: &[ToolDefinition],
-    ) -> Result<String> {
-        let api_url = format!(
-            "https://generativelanguage.googleapis.com/v1beta/models/gemini-2.0-flash:generateContent?key={}",
-            self.api_key
-        );
-        
-        // Format messages for the API
-        let mut formatted_messages = Vec::new();
-        
-        // Add system prompt
-        formatted_messages.push(json!({
-            "role": "user",
-            "parts": [
-                {
-                    "text": system_prompt
-                }
-            ]
-        }));
-        
-        // Add conversation messages
-        for (role, content) in messages {
-            formatted_messages.push(json!({
-                "role": role,
-                "parts": [
-                    {
-                        "text": content
-                    }
-                ]
-            }));
+    ) -> Result<ModelResponse> {
+        let api_url = format!("{}/models/{}:generateContent", self.base_url, self.model);
+        
+        let response = self.send_request(&api_url, system_prompt, messages, tools).await?;
+        
+        let response_json: Value = response.json().await?;
+        
+        // Log the full response for debugging
+        debug!("Received response from Gemini API: {}", serde_json::to_string_pretty(&response_json)?);
+        
+        let response: GenerateContentResponse = serde_json::from_value(response_json)?;
+        let parsed = parse_candidate(response);
+        
+        if let Some(error) = parsed.error {
+            return Err(error.into());
+        }
+        if parsed.response.text.is_empty() && parsed.response.function_calls.is_empty() {
+            return Err(ModelError::EmptyResponse { finish_reason: parsed.finish_reason }.into());
         }
         
-        // Format tools for the API
-        let formatted_tools = tools.iter().map(|tool| {
-            json!({
-                "functionDeclarations": [
-                    {
-                        "name": tool.name,
-                        "description": tool.description,
-                        "parameters": tool.parameters
-                    }
-                ]
-            })
-        }).collect::<Vec<_>>();
+        Ok(parsed.response)
+    }
+    
+    /// Stream a response using `streamGenerateContent` with server-sent events.
+    ///
+    /// Each item of the returned stream holds the text delta and any function
+    /// calls contained in one SSE chunk. Callers are expected to render text
+    /// as it arrives and accumulate function calls until the stream ends.
+    pub async fn stream_generate_content(
+        &self,
+        system_prompt: &str,
+        messages: &[Content],
+        tools: &[ToolDefinition],
+    ) -> Result<BoxStream<'static, Result<ModelResponse>>> {
+        let api_url = format!("{}/models/{}:streamGenerateContent?alt=sse", self.base_url, self.model);
+        
+        let response = self.send_request(&api_url, system_prompt, messages, tools).await?;
+        
+        let state = StreamState {
+            response,
+            decoder: SseDecoder::new(),
+            received_content: false,
+            finish_reason: None,
+            pending_error: None,
+            done: false,
+        };
+        
+        Ok(stream::unfold(state, next_stream_chunk).boxed())
+    }
+    
+    /// Count tokens for the prompt and history using the `countTokens` endpoint
+    pub async fn count_tokens(&self, system_prompt: &str, messages: &[Content]) -> Result<usize> {
+        let api_url = format!("{}/models/{}:countTokens", self.base_url, self.model);
         
+        let (system_instruction, contents) = format_contents(system_prompt, messages)?;
         let request_body = json!({
-            "contents": formatted_messages,
-            "tools": formatted_tools,
-            "generationConfig": {
-                "temperature": 0.2,
-                "topP": 0.8,
-                "topK": 40,
-                "maxOutputTokens": 8192
+            "generateContentRequest": {
+                "model": format!("models/{}", self.model),
+                "systemInstruction": system_instruction,
+                "contents": contents,
             }
         });
         
+        let response = self.post(&api_url, &request_body).await?;
+        let response: CountTokensResponse = response.json().await?;
+        
+        Ok(response.total_tokens)
+    }
+    
+    async fn send_request(
+        &self,
+        api_url: &str,
+        system_prompt: &str,
+        messages: &[Content],
+        tools: &[ToolDefinition],
+    ) -> Result<reqwest::Response> {
+        let request_body = build_request_body(system_prompt, messages, tools, &self.generation)?;
+        self.post(api_url, &request_body).await
+    }
+    
+    /// Send a request, retrying rate limited and temporarily failing
+    /// requests according to the retry policy.
+    async fn post(&self, api_url: &str, request_body: &Value) -> Result<reqwest::Response> {
         // Log the request for debugging
         debug!("Sending request to Gemini API: {}", serde_json::to_string_pretty(&request_body)?);
         
-        let response = self.client.post(&api_url)
-            .json(&request_body)
-            .send()
-            .await?;
-        
-        if !response.status().is_success() {
-            let error_text = response.text().await?;
-            error!("API request failed with response: {}", error_text);
-            return Err(eyre!("API request failed: {}", error_text));
-        }
-        
-        let response_json: Value = response.json().await?;
-        
-        // Log the full response for debugging
-        debug!("Received response from Gemini API: {}", serde_json::to_string_pretty(&response_json)?);
+        let max_attempts = self.retry.max_attempts.max(1);
+        let mut attempt = 1;
         
-        // Handle different response types
-        if let Some(candidates) = response_json.get("candidates") {
-            if let Some(first_candidate) = candidates.as_array().and_then(|arr| arr.first()) {
-                // Check for error conditions
-                if let Some(finish_reason) = first_candidate.get("finishReason") {
-                    if finish_reason == "MALFORMED_FUNCTION_CALL" {
-                        info!("Received MALFORMED_FUNCTION_CALL, using direct command approach");
-                        return Ok(format!(
-                            "<function_calls>\n<invoke name=\"execute_bash\">\n<parameter name=\"command\">ls -la</parameter>\n</invoke>\n</function_calls>\n\nI need to examine the project files to explain this project. Let me start by listing the files in the current directory."
-                        ));
+        loop {
+            let result = self.client.post(api_url)
+                .header("x-goog-api-key", &self.api_key)
+                .json(request_body)
+                .send()
+                .await;
+            
+            let (reason, delay) = match result {
+                Ok(response) if response.status().is_success() => return Ok(response),
+                Ok(response) => {
+                    let status = response.status();
+                    let retry_after = retry::retry_after(response.headers());
+                    let error_text = response.text().await?;
+                    error!("API request failed with response: {}", error_text);
+                    
+                    let error = ModelError::from_response_body(status.as_u16(), &error_text);
+                    if !retry::is_retryable(status) || attempt >= max_attempts {
+                        return Err(error.into());
                     }
-                }
-                
-                // Try to extract content
-                if let Some(content) = first_candidate.get("content") {
-                    if let Some(parts) = content.get("parts") {
-                        if let Some(parts_array) = parts.as_array() {
-                            // Process all parts
-                            let mut result = String::new();
-                            
-                            for part in parts_array {
-                                // Check if this is a function call
-                                if let Some(function_call) = part.get("functionCall") {
-                                    let name = function_call.get("name").and_then(|n| n.as_str()).unwrap_or("unknown");
-                                    
-                                    // Create a temporary value for args
-                                    let empty_json = json!({});
-                                    let args = function_call.get("args").unwrap_or(&empty_json);
-                                    
-                                    // Format as a function call string
-                                    let function_call_str = format!(
-                                        "<function_calls>\n<invoke name=\"{}\">\n{}\n</invoke>\n</function_calls>",
-                                        name,
-                                        format_args(args)
-                                    );
-                                    
-                                    result.push_str(&function_call_str);
-                                }
-                                
-                                // Regular text response
-                                if let Some(text) = part.get("text").and_then(|t| t.as_str()) {
-                                    result.push_str(text);
-                                }
-                            }
-                            
-                            if !result.is_empty() {
-                                return Ok(result);
-                            }
-                        }
+                    
+                    // Prefer the delay requested by the server over our own backoff
+                    let delay = retry_after
+                        .or_else(|| retry::retry_info_delay(&error_text))
+                        .unwrap_or_else(|| self.retry.backoff(attempt));
+                    if delay > retry::MAX_DELAY {
+                        return Err(error.into());
                     }
+                    (error.to_string(), delay)
+                }
+                Err(e) if (e.is_timeout() || e.is_connect()) && attempt < max_attempts => {
+                    (format!("Request failed: {}", e), self.retry.backoff(attempt))
                 }
+                Err(e) => return Err(e.into()),
+            };
+            
+            attempt += 1;
+            let notice = RetryNotice { reason, delay, attempt, max_attempts };
+            debug!("{}", notice);
+            if let Some(notifier) = &self.retry_notifier {
+                let _ = notifier.send(notice);
             }
+            
+            tokio::time::sleep(delay).await;
+        }
+    }
+}
+
+#[async_trait]
+impl ModelProvider for GeminiClient {
+    fn name(&self) -> String {
+        format!("Gemini ({})", self.model)
+    }
+    
+    fn supports_tools(&self) -> bool {
+        true
+    }
+    
+    fn context_window(&self) -> usize {
+        CONTEXT_WINDOW
+    }
+    
+    async fn generate(
+        &self,
+        system_prompt: &str,
+        messages: &[Content],
+        tools: &[ToolDefinition],
+    ) -> Result<ModelResponse> {
+        self.generate_content(system_prompt, messages, tools).await
+    }
+    
+    async fn stream(
+        &self,
+        system_prompt: &str,
+        messages: &[Content],
+        tools: &[ToolDefinition],
+    ) -> Result<BoxStream<'static, Result<ModelResponse>>> {
+        self.stream_generate_content(system_prompt, messages, tools).await
+    }
+    
+    async fn count_tokens(&self, system_prompt: &str, messages: &[Content]) -> Result<usize> {
+        GeminiClient::count_tokens(self, system_prompt, messages).await
+    }
+    
+    fn set_retry_notifier(&mut self, notifier: UnboundedSender<RetryNotice>) {
+        self.retry_notifier = Some(notifier);
+    }
+}
+
+fn build_request_body(
+    system_prompt: &str,
+    messages: &[Content],
+    tools: &[ToolDefinition],
+    generation: &GenerationConfig,
+) -> Result<Value> {
+    let (system_instruction, formatted_messages) = format_contents(system_prompt, messages)?;
+    
+    // Format tools for the API
+    let formatted_tools = tools.iter().map(|tool| {
+        json!({
+            "functionDeclarations": [
+                {
+                    "name": tool.name,
+                    "description": tool.description,
+                    "parameters": sanitize_schema(&tool.parameters)
+                }
+            ]
+        })
+    }).collect::<Vec<_>>();
+    
+    Ok(json!({
+        "systemInstruction": system_instruction,
+        "contents": formatted_messages,
+        "tools": formatted_tools,
+        "generationConfig": {
+            "temperature": generation.temperature.unwrap_or(0.2),
+            "topP": generation.top_p.unwrap_or(0.8),
+            "topK": generation.top_k.unwrap_or(40),
+            "maxOutputTokens": generation.max_output_tokens.unwrap_or(8192)
         }
+    }))
+}
+
+/// Convert the conversation into Gemini's `systemInstruction` and
+/// `contents`.
+///
+/// Gemini only accepts the `user` and `model` roles, so function results are
+/// sent as `user` turns and system messages are folded into the system
+/// instruction. Consecutive turns that end up with the same role are merged,
+/// as the API rejects them otherwise.
+fn format_contents(system_prompt: &str, messages: &[Content]) -> Result<(Value, Vec<Value>)> {
+    let mut system_text = system_prompt.to_string();
+    let mut contents: Vec<(&str, Vec<Value>)> = Vec::new();
+    
+    for message in messages {
+        let role = match message.role {
+            Role::System => {
+                system_text.push_str("\n\n");
+                system_text.push_str(&message.text());
+                continue;
+            }
+            Role::User | Role::Function => "user",
+            Role::Model => "model",
+        };
+        
+        let parts = message.parts.iter()
+            .map(serde_json::to_value)
+            .collect::<Result<Vec<_>, _>>()?;
         
-        // If we get here, we couldn't extract the text or there was an error
-        info!("Could not extract proper response, using fallback");
-        return Ok(format!(
-            "<function_calls>\n<invoke name=\"execute_bash\">\n<parameter name=\"command\">ls -la</parameter>\n</invoke>\n</function_calls>\n\nI need to examine the project files to explain this project. Let me start by listing the files in the current directory."
-        ));
+        match contents.last_mut() {
+            Some((last_role, last_parts)) if *last_role == role => last_parts.extend(parts),
+            _ => contents.push((role, parts)),
+        }
     }
+    
+    let system_instruction = json!({
+        "parts": [
+            {
+                "text": system_text
+            }
+        ]
+    });
+    
+    let contents = contents.into_iter()
+        .map(|(role, parts)| json!({ "role": role, "parts": parts }))
+        .collect();
+    
+    Ok((system_instruction, contents))
 }
 
-fn format_args(args: &Value) -> String {
-    let mut result = String::new();
+/// Gemini rejects object schemas without any properties, so free-form object
+/// parameters are declared as JSON-encoded strings instead. Tools accepting
+/// such parameters must handle both forms.
+fn sanitize_schema(schema: &Value) -> Value {
+    let Some(object) = schema.as_object() else {
+        return schema.clone();
+    };
     
-    if let Some(obj) = args.as_object() {
-        for (key, value) in obj {
-            let value_str = match value {
-                Value::String(s) => s.clone(),
-                _ => value.to_string(),
-            };
+    let is_object = object.get("type").and_then(|t| t.as_str()) == Some("object");
+    let has_properties = object.get("properties")
+        .and_then(|p| p.as_object())
+        .is_some_and(|p| !p.is_empty());
+    
+    if is_object && !has_properties {
+        let description = object.get("description").and_then(|d| d.as_str()).unwrap_or("");
+        return json!({
+            "type": "string",
+            "description": format!("{} Pass the object as a JSON-encoded string.", description).trim(),
+        });
+    }
+    
+    let mut sanitized = object.clone();
+    if let Some(properties) = object.get("properties").and_then(|p| p.as_object()) {
+        let properties = properties.iter()
+            .map(|(name, property)| (name.clone(), sanitize_schema(property)))
+            .collect();
+        sanitized.insert("properties".to_string(), Value::Object(properties));
+    }
+    if let Some(items) = object.get("items") {
+        sanitized.insert("items".to_string(), sanitize_schema(items));
+    }
+    
+    Value::Object(sanitized)
+}
+
+/// The content of the first candidate along with the error its finish
+/// reason indicates, if any
+struct ParsedCandidate {
+    response: ModelResponse,
+    finish_reason: Option<String>,
+    error: Option<ModelError>,
+}
+
+/// Split the first candidate into text and function calls, and translate
+/// blocked prompts and abnormal finish reasons into a `ModelError`.
+fn parse_candidate(response: GenerateContentResponse) -> ParsedCandidate {
+    let mut parsed = ParsedCandidate {
+        response: ModelResponse::default(),
+        finish_reason: None,
+        error: None,
+    };
+    
+    if let Some(reason) = response.prompt_feedback.and_then(|feedback| feedback.block_reason) {
+        parsed.error = Some(ModelError::Blocked { reason });
+        return parsed;
+    }
+    
+    let Some(first_candidate) = response.candidates.into_iter().next() else {
+        return parsed;
+    };
+    
+    if let Some(content) = first_candidate.content {
+        for part in content.parts {
+            if let Some(function_call) = part.function_call {
+                parsed.response.function_calls.push(function_call);
+            }
             
-            result.push_str(&format!("<parameter name=\"{}\">{}</parameter>\n", key, value_str));
+            if let Some(text) = part.text {
+                parsed.response.text.push_str(&text);
+            }
         }
     }
     
-    result
+    parsed.error = match first_candidate.finish_reason.as_deref() {
+        Some(reason @ ("SAFETY" | "BLOCKLIST" | "PROHIBITED_CONTENT" | "SPII" | "IMAGE_SAFETY")) => {
+            Some(ModelError::Blocked { reason: reason.to_string() })
+        }
+        Some("RECITATION") => Some(ModelError::Recitation),
+        Some("MAX_TOKENS") => Some(ModelError::MaxTokens),
+        Some("MALFORMED_FUNCTION_CALL") => Some(ModelError::MalformedFunctionCall {
+            message: first_candidate.finish_message,
+        }),
+        _ => None,
+    };
+    parsed.finish_reason = first_candidate.finish_reason;
+    
+    parsed
+}
+
+struct StreamState {
+    response: reqwest::Response,
+    decoder: SseDecoder,
+    received_content: bool,
+    finish_reason: Option<String>,
+    /// An error reported alongside content, returned after that content
+    pending_error: Option<ModelError>,
+    done: bool,
+}
+
+async fn next_stream_chunk(mut state: StreamState) -> Option<(Result<ModelResponse>, StreamState)> {
+    loop {
+        if let Some(error) = state.pending_error.take() {
+            state.done = true;
+            return Some((Err(error.into()), state));
+        }
+        
+        if state.done {
+            if state.received_content {
+                return None;
+            }
+            state.received_content = true;
+            let finish_reason = state.finish_reason.take();
+            return Some((Err(ModelError::EmptyResponse { finish_reason }.into()), state));
+        }
+        
+        // Drain any complete events before reading more bytes
+        let event = match state.decoder.next_event() {
+            Some(event) => Some(event),
+            None => match state.response.chunk().await {
+                Ok(Some(bytes)) => {
+                    state.decoder.push(&bytes);
+                    continue;
+                }
+                Ok(None) => {
+                    state.done = true;
+                    state.decoder.finish()
+                }
+                Err(e) => {
+                    state.done = true;
+                    state.received_content = true;
+                    return Some((Err(e.into()), state));
+                }
+            },
+        };
+        
+        let Some(event) = event else {
+            continue;
+        };
+        
+        debug!("Received stream chunk from Gemini API: {}", event);
+        
+        let chunk: GenerateContentResponse = match serde_json::from_str(&event) {
+            Ok(chunk) => chunk,
+            Err(e) => {
+                state.done = true;
+                state.received_content = true;
+                return Some((Err(e.into()), state));
+            }
+        };
+        
+        let parsed = parse_candidate(chunk);
+        if parsed.finish_reason.is_some() {
+            state.finish_reason = parsed.finish_reason;
+        }
+        
+        let has_content = !parsed.response.text.is_empty() || !parsed.response.function_calls.is_empty();
+        
+        if let Some(error) = parsed.error {
+            state.received_content = true;
+            state.done = true;
+            if !has_content {
+                return Some((Err(error.into()), state));
+            }
+            // Deliver the partial content before the error
+            state.pending_error = Some(error);
+        }
+        
+        if has_content {
+            state.received_content = true;
+            return Some((Ok(parsed.response), state));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tokio::sync::mpsc;
+
+    use super::*;
+    use crate::mock_server::{self, MockServer};
+
+    fn client(server: &MockServer, notifier: UnboundedSender<RetryNotice>) -> GeminiClient {
+        GeminiClient {
+            api_key: String::from("test-key"),
+            base_url: server.url.clone(),
+            model: DEFAULT_MODEL.to_string(),
+            generation: GenerationConfig::default(),
+            retry: RetryPolicy::default(),
+            retry_notifier: Some(notifier),
+            client: reqwest::Client::new(),
+        }
+    }
+
+    fn prompt() -> Vec<Content> {
+        vec![Content::text_message(Role::User, "Hi")]
+    }
+
+    #[tokio::test]
+    async fn rate_limited_request_is_retried() {
+        let reply = json!({ "candidates": [{ "content": { "parts": [{ "text": "Hello" }] } }] }).to_string();
+        let server = MockServer::start(vec![
+            mock_server::response(429, &[("Retry-After", "0")], r#"{"error": {"code": 429, "message": "Slow down"}}"#),
+            mock_server::response(200, &[("Content-Type", "application/json")], &reply),
+        ])
+        .await;
+        let (notifier, mut notices) = mpsc::unbounded_channel();
+
+        let response = client(&server, notifier).generate_content("", &prompt(), &[]).await.unwrap();
+
+        assert_eq!(response.text, "Hello");
+        assert_eq!(server.requests().len(), 2);
+        let notice = notices.try_recv().unwrap();
+        assert_eq!(notice.attempt, 2);
+        assert_eq!(notice.delay, Duration::ZERO);
+        assert!(notices.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn bad_request_fails_immediately() {
+        let server = MockServer::start(vec![mock_server::response(
+            400,
+            &[("Content-Type", "application/json")],
+            r#"{"error": {"code": 400, "message": "Invalid argument", "status": "INVALID_ARGUMENT"}}"#,
+        )])
+        .await;
+        let (notifier, mut notices) = mpsc::unbounded_channel();
+
+        let error = client(&server, notifier).generate_content("", &prompt(), &[]).await.unwrap_err();
+
+        assert!(error.to_string().contains("Invalid argument"), "{}", error);
+        assert_eq!(server.requests().len(), 1);
+        assert!(notices.try_recv().is_err());
+    }
 }
@@ -1,16 +1,30 @@
-mod gemini_client;
 mod cli;
+mod config;
+mod gemini_client;
+#[cfg(test)]
+mod mock_server;
+mod model_provider;
+mod ollama_client;
+mod openai_client;
+mod redact;
+mod retry;
+mod sse;
 
-use std::io;
+use std::env;
+use std::io::{self, IsTerminal};
 use std::process::ExitCode;
 
-use clap::{Parser, Subcommand};
+use clap::{Args, Parser, Subcommand};
 use dotenv::dotenv;
 use eyre::Result;
-use tracing::{Level, info};
+use tracing::{Level, info, warn};
 use tracing_subscriber::FmtSubscriber;
 
+use crate::cli::chat::input_source::{self, InputSource};
 use crate::cli::chat::ChatContext;
+use crate::config::Config;
+use crate::model_provider::ProviderKind;
+use crate::redact::RedactingMakeWriter;
 
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
@@ -18,7 +32,24 @@ struct Cli {
     #[command(subcommand)]
     command: Option<Commands>,
     
-    /// Input to send to the chat
+    #[command(flatten)]
+    chat: ChatArgs,
+}
+
+#[derive(Subcommand)]
+enum Commands {
+    /// Start a chat session
+    Chat {
+        #[command(flatten)]
+        chat: ChatArgs,
+    },
+}
+
+/// Options shared by the top level command and the `chat` subcommand.
+/// Values given here override the configuration files.
+#[derive(Args)]
+struct ChatArgs {
+    /// Input to send to the chat. Content piped through stdin is attached to it.
     #[arg(short, long)]
     input: Option<String>,
     
@@ -26,68 +57,134 @@ struct Cli {
     #[arg(short, long)]
     yes: bool,
     
+    /// Maximum number of tool call rounds per message [default: 25]
+    #[arg(long)]
+    max_steps: Option<usize>,
+    
+    /// Model backend to use [default: gemini]
+    #[arg(long, value_enum)]
+    provider: Option<ProviderKind>,
+    
+    /// Model name to request from the provider
+    #[arg(long)]
+    model: Option<String>,
+    
+    /// API base URL, e.g. to go through a proxy or use a local mock server
+    #[arg(long, value_name = "URL")]
+    base_url: Option<String>,
+    
+    /// Resume a saved session, or the most recent one if no name is given
+    #[arg(long, value_name = "NAME", num_args = 0..=1, default_missing_value = "")]
+    resume: Option<String>,
+    
+    /// Maximum tokens to send per request before older history is compacted
+    #[arg(long, value_name = "TOKENS")]
+    context_budget: Option<usize>,
+    
+    /// Run tools in a sandbox that can only write to the current directory
+    /// and has no network (Linux only, needs bubblewrap)
+    #[arg(long)]
+    sandbox: bool,
+    
     /// Enable verbose logging
     #[arg(short, long)]
     verbose: bool,
 }
 
-#[derive(Subcommand)]
-enum Commands {
-    /// Start a chat session
-    Chat {
-        /// Input to send to the chat
-        #[arg(short, long)]
-        input: Option<String>,
-        
-        /// Enable verbose logging
-        #[arg(short, long)]
-        verbose: bool,
-    },
+impl ChatArgs {
+    /// Apply the command line flags on top of the loaded configuration
+    fn apply_to(&self, config: &mut Config) {
+        if self.yes {
+            config.approval.accept_all = Some(true);
+        }
+        if self.max_steps.is_some() {
+            config.max_steps = self.max_steps;
+        }
+        if self.provider.is_some() {
+            config.provider = self.provider;
+        }
+        if self.model.is_some() {
+            config.model = self.model.clone();
+        }
+        if self.base_url.is_some() {
+            config.base_url = self.base_url.clone();
+        }
+        if self.context_budget.is_some() {
+            config.context_budget = self.context_budget;
+        }
+        if self.sandbox {
+            config.sandbox.enabled = Some(true);
+        }
+    }
 }
 
+/// Environment variables that redirect the API requests, and the API key
+/// with them. They are not read from `.env` files, which come with the
+/// project like a project config file.
+const BASE_URL_VARS: &[&str] = &["GEMINI_BASE_URL", "OPENAI_BASE_URL"];
+
 #[tokio::main]
 async fn main() -> Result<ExitCode> {
     // Load environment variables from .env file
+    let unset_vars: Vec<&str> = BASE_URL_VARS.iter().copied().filter(|var| env::var_os(var).is_none()).collect();
     dotenv().ok();
+    let ignored_vars: Vec<&str> = unset_vars.into_iter().filter(|var| env::var_os(var).is_some()).collect();
+    for var in &ignored_vars {
+        env::remove_var(var);
+    }
     
     let cli = Cli::parse();
     
-    // Initialize tracing with appropriate level
-    let verbose = match &cli.command {
-        Some(Commands::Chat { verbose, .. }) => *verbose,
-        None => cli.verbose,
+    // Default to chat if no subcommand is provided
+    let args = match cli.command {
+        Some(Commands::Chat { chat }) => chat,
+        None => cli.chat,
     };
     
-    let log_level = if verbose { Level::DEBUG } else { Level::INFO };
+    // Initialize tracing with appropriate level
+    let log_level = if args.verbose { Level::DEBUG } else { Level::INFO };
+    
+    // Keep API keys and other credentials out of the logs
+    redact::register_env_secrets();
     
     let subscriber = FmtSubscriber::builder()
         .with_max_level(log_level)
+        .with_writer(RedactingMakeWriter::new(io::stdout))
         .finish();
     
     tracing::subscriber::set_global_default(subscriber)
         .expect("Failed to set tracing subscriber");
     
     info!("Starting Gemini Chat CLI");
+    for var in ignored_vars {
+        warn!("Ignoring {} in .env: set it in the environment or use --base-url", var);
+    }
     
-    match cli.command {
-        Some(Commands::Chat { input, .. }) => {
-            let mut chat_context = ChatContext::new(
-                Box::new(io::stdout()),
-                input,
-                true,
-                cli.yes,
-            );
-            chat_context.run().await
-        }
-        None => {
-            // Default to chat if no subcommand is provided
-            let mut chat_context = ChatContext::new(
-                Box::new(io::stdout()),
-                cli.input,
-                true,
-                cli.yes,
-            );
-            chat_context.run().await
-        }
+    let mut config = Config::load()?;
+    args.apply_to(&mut config);
+    
+    // Piped stdin is read as the prompt, or attached to the one from --input
+    let prompt = if io::stdin().is_terminal() {
+        args.input
+    } else {
+        Some(input_source::prompt_with_stdin(args.input)?)
+    };
+    let interactive = prompt.is_none();
+    let input_source = match prompt {
+        Some(prompt) => InputSource::lines([prompt]),
+        None => InputSource::readline(&config.history)?,
+    };
+    
+    let mut chat_context = ChatContext::new(
+        Box::new(io::stdout()),
+        input_source,
+        interactive,
+        config,
+    )?;
+    
+    if let Some(name) = args.resume {
+        chat_context.resume(Some(name.as_str()).filter(|name| !name.is_empty()))?;
     }
+    
+    chat_context.run().await
 }
@@ -0,0 +1,283 @@
+use async_trait::async_trait;
+use clap::ValueEnum;
+use eyre::Result;
+use futures::stream::BoxStream;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tokio::sync::mpsc::UnboundedSender;
+
+use crate::gemini_client::GeminiClient;
+use crate::ollama_client::OllamaClient;
+use crate::openai_client::OpenAiClient;
+use crate::retry::{RetryNotice, RetryPolicy};
+
+/// A tool the model is allowed to call, described by a JSON schema
+pub struct ToolDefinition {
+    pub name: String,
+    pub description: String,
+    pub parameters: Value,
+}
+
+/// A function call requested by the model, with its arguments kept as raw JSON.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FunctionCall {
+    /// Provider-assigned identifier used to match the call with its response
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+
+    pub name: String,
+
+    #[serde(default = "empty_args")]
+    pub args: Value,
+}
+
+fn empty_args() -> Value {
+    json!({})
+}
+
+/// The result of a function call, sent back to the model as a `functionResponse` part.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FunctionResponse {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+
+    pub name: String,
+
+    pub response: Value,
+}
+
+/// A single part of a conversation turn, serialized in the Gemini wire format.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Part {
+    Text(String),
+    FunctionCall(FunctionCall),
+    FunctionResponse(FunctionResponse),
+}
+
+/// The author of a conversation turn.
+///
+/// Providers map these onto their own role names: Gemini only knows `user`
+/// and `model`, while chat completion APIs use `assistant` and `tool`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    /// Instructions for the model, outside of the conversation itself
+    System,
+
+    /// A message written by the user
+    User,
+
+    /// Text and function calls produced by the model. Sessions saved before
+    /// roles were typed used `assistant` for model text.
+    #[serde(alias = "assistant")]
+    Model,
+
+    /// Results of the model's function calls
+    Function,
+}
+
+/// A conversation turn consisting of a role and one or more parts.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Content {
+    pub role: Role,
+    pub parts: Vec<Part>,
+}
+
+impl Content {
+    pub fn new(role: Role, parts: Vec<Part>) -> Self {
+        Self { role, parts }
+    }
+
+    /// A turn consisting of a single text part
+    pub fn text_message(role: Role, text: impl Into<String>) -> Self {
+        Self::new(role, vec![Part::Text(text.into())])
+    }
+
+    /// Concatenated text parts of this turn
+    pub fn text(&self) -> String {
+        self.parts
+            .iter()
+            .filter_map(|part| match part {
+                Part::Text(text) => Some(text.as_str()),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+/// The typed result of a model call, or one chunk of a streamed call.
+#[derive(Debug, Clone, Default)]
+pub struct ModelResponse {
+    /// Concatenated text parts of the first candidate
+    pub text: String,
+
+    /// Function calls requested by the model, in the order they were returned
+    pub function_calls: Vec<FunctionCall>,
+}
+
+/// Reasons a model call produced no usable response.
+///
+/// Providers return these wrapped in an `eyre::Report` so that `ChatContext`
+/// can tell them apart from transport failures with `downcast_ref`.
+#[derive(Debug, thiserror::Error)]
+pub enum ModelError {
+    /// The prompt or the response was blocked by the provider's safety filters
+    #[error("The response was blocked by the model's safety filters ({reason}).")]
+    Blocked { reason: String },
+
+    /// The response was stopped because it repeated copyrighted material
+    #[error("The response was stopped because it closely recited existing material. Try rephrasing the request.")]
+    Recitation,
+
+    /// The response hit the output token limit before completing
+    #[error("The response was cut off after reaching the maximum output length. Raise generation.max_output_tokens or ask for a shorter answer.")]
+    MaxTokens,
+
+    /// The model tried to call a function but produced an invalid call
+    #[error("The model produced a malformed function call{}", .message.as_deref().map(|m| format!(": {}", m)).unwrap_or_default())]
+    MalformedFunctionCall { message: Option<String> },
+
+    /// The response contained neither text nor function calls
+    #[error("The model returned an empty response{}.", .finish_reason.as_deref().map(|r| format!(" (finish reason: {})", r)).unwrap_or_default())]
+    EmptyResponse { finish_reason: Option<String> },
+
+    /// The API rejected the request
+    #[error("API request failed with status {status}: {message}")]
+    Api { status: u16, message: String },
+}
+
+impl ModelError {
+    /// Build an `Api` error from an HTTP error response, extracting the
+    /// `error.message` field used by Gemini and OpenAI-compatible APIs (or
+    /// the plain `error` string used by Ollama) when present.
+    pub fn from_response_body(status: u16, body: &str) -> Self {
+        let json = serde_json::from_str::<Value>(body).ok();
+        let message = json.as_ref()
+            .and_then(|json| {
+                let error = json.get("error")?;
+                error.get("message").and_then(|m| m.as_str()).or_else(|| error.as_str())
+            })
+            .map(str::to_string)
+            .unwrap_or_else(|| body.trim().to_string());
+
+        ModelError::Api { status, message }
+    }
+}
+
+/// Sampling parameters for a model. Unset values use the provider's defaults.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GenerationConfig {
+    pub temperature: Option<f32>,
+    pub top_p: Option<f32>,
+    pub top_k: Option<u32>,
+    pub max_output_tokens: Option<u32>,
+}
+
+/// The model backends that can be selected with `--provider`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ProviderKind {
+    /// Google Gemini via the Generative Language API
+    Gemini,
+
+    /// Any server implementing the OpenAI chat completions API
+    Openai,
+
+    /// A local Ollama server
+    Ollama,
+}
+
+/// A backend capable of generating chat responses.
+///
+/// `ChatContext` talks to the model exclusively through this trait so that
+/// the chat loop, tool execution and conversation state are independent of
+/// the API in use.
+#[async_trait]
+pub trait ModelProvider: Send + Sync {
+    /// Human readable name of the provider and model, for messages
+    fn name(&self) -> String;
+
+    /// Whether the backend accepts tool definitions and returns function calls
+    fn supports_tools(&self) -> bool;
+
+    /// Maximum number of input tokens the model accepts
+    fn context_window(&self) -> usize;
+
+    /// Generate a complete response in a single request
+    async fn generate(
+        &self,
+        system_prompt: &str,
+        messages: &[Content],
+        tools: &[ToolDefinition],
+    ) -> Result<ModelResponse>;
+
+    /// Stream a response. Each item holds a text delta and any function
+    /// calls that are complete at that point.
+    async fn stream(
+        &self,
+        system_prompt: &str,
+        messages: &[Content],
+        tools: &[ToolDefinition],
+    ) -> Result<BoxStream<'static, Result<ModelResponse>>>;
+
+    /// Count the tokens the given prompt and history will consume
+    async fn count_tokens(&self, system_prompt: &str, messages: &[Content]) -> Result<usize> {
+        let mut total = estimate_tokens(system_prompt);
+        for message in messages {
+            total += estimate_message_tokens(message);
+        }
+        Ok(total)
+    }
+
+    /// Receive a notice whenever a failed request is about to be retried.
+    /// Providers that don't retry requests ignore the notifier.
+    fn set_retry_notifier(&mut self, _notifier: UnboundedSender<RetryNotice>) {}
+}
+
+/// Create the configured provider
+pub fn create_provider(
+    kind: ProviderKind,
+    model: Option<String>,
+    base_url: Option<String>,
+    generation: GenerationConfig,
+    retry: RetryPolicy,
+) -> Result<Box<dyn ModelProvider>> {
+    Ok(match kind {
+        ProviderKind::Gemini => Box::new(GeminiClient::new(model, base_url, generation, retry)?),
+        ProviderKind::Openai => Box::new(OpenAiClient::new(model, base_url, generation)?),
+        ProviderKind::Ollama => Box::new(OllamaClient::new(model, base_url, generation)?),
+    })
+}
+
+/// Rough token estimate for backends without a token counting endpoint,
+/// assuming about four characters per token
+pub fn estimate_tokens(text: &str) -> usize {
+    text.chars().count().div_ceil(4)
+}
+
+/// Rough token estimate for a whole conversation turn
+pub fn estimate_message_tokens(message: &Content) -> usize {
+    message
+        .parts
+        .iter()
+        .map(|part| match part {
+            Part::Text(text) => estimate_tokens(text),
+            Part::FunctionCall(call) => estimate_tokens(&call.name) + estimate_tokens(&call.args.to_string()),
+            Part::FunctionResponse(response) => {
+                estimate_tokens(&response.name) + estimate_tokens(&response.response.to_string())
+            },
+        })
+        .sum()
+}
+
+/// Rough token estimate for the tool declarations sent with every request
+pub fn estimate_tool_tokens(tools: &[ToolDefinition]) -> usize {
+    tools
+        .iter()
+        .map(|tool| {
+            estimate_tokens(&tool.name) + estimate_tokens(&tool.description) + estimate_tokens(&tool.parameters.to_string())
+        })
+        .sum()
+}
@@ -0,0 +1,320 @@
+use std::env;
+
+use async_trait::async_trait;
+use eyre::{Result, eyre};
+use futures::stream::{self, BoxStream, StreamExt};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tracing::{debug, error};
+
+use crate::model_provider::{
+    Content,
+    FunctionCall,
+    GenerationConfig,
+    ModelError,
+    ModelProvider,
+    ModelResponse,
+    Part,
+    Role,
+    ToolDefinition,
+};
+
+const DEFAULT_HOST: &str = "http://localhost:11434";
+const DEFAULT_MODEL: &str = "llama3.1";
+
+/// Context size assumed for local models; Ollama truncates anything beyond its `num_ctx`
+const CONTEXT_WINDOW: usize = 8_192;
+
+#[derive(Debug, Deserialize)]
+struct ChatResponse {
+    message: Option<ChatMessage>,
+    error: Option<String>,
+    #[serde(default)]
+    done: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChatMessage {
+    #[serde(default)]
+    content: String,
+    #[serde(default)]
+    tool_calls: Vec<ToolCall>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ToolCall {
+    function: ToolCallFunction,
+}
+
+#[derive(Debug, Deserialize)]
+struct ToolCallFunction {
+    name: String,
+    #[serde(default)]
+    arguments: Value,
+}
+
+/// Client for a local Ollama server using the `/api/chat` endpoint.
+///
+/// The server address is taken from the configured base URL or `OLLAMA_HOST`
+/// and no API key is needed, which makes this the backend of choice on
+/// air-gapped machines.
+pub struct OllamaClient {
+    host: String,
+    model: String,
+    generation: GenerationConfig,
+    client: reqwest::Client,
+}
+
+impl OllamaClient {
+    pub fn new(model: Option<String>, base_url: Option<String>, generation: GenerationConfig) -> Result<Self> {
+        let host = base_url
+            .or_else(|| env::var("OLLAMA_HOST").ok())
+            .unwrap_or_else(|| DEFAULT_HOST.to_string());
+        
+        // OLLAMA_HOST is commonly set without a scheme, e.g. `0.0.0.0:11434`
+        let host = if host.contains("://") { host } else { format!("http://{}", host) };
+        
+        Ok(Self {
+            host: host.trim_end_matches('/').to_string(),
+            model: model
+                .or_else(|| env::var("OLLAMA_MODEL").ok())
+                .unwrap_or_else(|| DEFAULT_MODEL.to_string()),
+            generation,
+            client: reqwest::Client::new(),
+        })
+    }
+    
+    async fn send_request(
+        &self,
+        system_prompt: &str,
+        messages: &[Content],
+        tools: &[ToolDefinition],
+        stream: bool,
+    ) -> Result<reqwest::Response> {
+        let api_url = format!("{}/api/chat", self.host);
+        
+        let mut request_body = json!({
+            "model": self.model,
+            "messages": format_messages(system_prompt, messages),
+            "stream": stream,
+            "options": {
+                "temperature": self.generation.temperature.unwrap_or(0.2),
+                "num_ctx": CONTEXT_WINDOW
+            }
+        });
+        
+        if let Some(top_p) = self.generation.top_p {
+            request_body["options"]["top_p"] = json!(top_p);
+        }
+        if let Some(top_k) = self.generation.top_k {
+            request_body["options"]["top_k"] = json!(top_k);
+        }
+        if let Some(max_output_tokens) = self.generation.max_output_tokens {
+            request_body["options"]["num_predict"] = json!(max_output_tokens);
+        }
+        
+        if !tools.is_empty() {
+            request_body["tools"] = tools.iter().map(|tool| {
+                json!({
+                    "type": "function",
+                    "function": {
+                        "name": tool.name,
+                        "description": tool.description,
+                        "parameters": tool.parameters
+                    }
+                })
+            }).collect();
+        }
+        
+        debug!("Sending request to Ollama: {}", serde_json::to_string_pretty(&request_body)?);
+        
+        let response = self.client.post(&api_url)
+            .json(&request_body)
+            .send()
+            .await
+            .map_err(|e| eyre!("Failed to reach Ollama at {}: {}", self.host, e))?;
+        
+        let status = response.status();
+        if !status.is_success() {
+            let error_text = response.text().await?;
+            error!("API request failed with response: {}", error_text);
+            return Err(ModelError::from_response_body(status.as_u16(), &error_text).into());
+        }
+        
+        Ok(response)
+    }
+}
+
+#[async_trait]
+impl ModelProvider for OllamaClient {
+    fn name(&self) -> String {
+        format!("Ollama ({})", self.model)
+    }
+    
+    fn supports_tools(&self) -> bool {
+        true
+    }
+    
+    fn context_window(&self) -> usize {
+        CONTEXT_WINDOW
+    }
+    
+    async fn generate(
+        &self,
+        system_prompt: &str,
+        messages: &[Content],
+        tools: &[ToolDefinition],
+    ) -> Result<ModelResponse> {
+        let response = self.send_request(system_prompt, messages, tools, false).await?;
+        let response_json: Value = response.json().await?;
+        
+        debug!("Received response from Ollama: {}", serde_json::to_string_pretty(&response_json)?);
+        
+        let response: ChatResponse = serde_json::from_value(response_json)?;
+        into_model_response(response)
+    }
+    
+    async fn stream(
+        &self,
+        system_prompt: &str,
+        messages: &[Content],
+        tools: &[ToolDefinition],
+    ) -> Result<BoxStream<'static, Result<ModelResponse>>> {
+        let response = self.send_request(system_prompt, messages, tools, true).await?;
+        
+        let state = StreamState {
+            response,
+            buffer: Vec::new(),
+            done: false,
+        };
+        
+        Ok(stream::unfold(state, next_stream_chunk).boxed())
+    }
+}
+
+fn format_messages(system_prompt: &str, messages: &[Content]) -> Vec<Value> {
+    let mut formatted = vec![json!({ "role": "system", "content": system_prompt })];
+    
+    for message in messages {
+        let mut tool_calls = Vec::new();
+        let mut has_tool_results = false;
+        
+        for part in &message.parts {
+            match part {
+                Part::Text(_) => {},
+                Part::FunctionCall(call) => tool_calls.push(json!({
+                    "function": {
+                        "name": call.name,
+                        "arguments": call.args
+                    }
+                })),
+                Part::FunctionResponse(response) => {
+                    has_tool_results = true;
+                    formatted.push(json!({
+                        "role": "tool",
+                        "content": response.response.to_string()
+                    }));
+                }
+            }
+        }
+        
+        if has_tool_results {
+            continue;
+        }
+        
+        let role = match message.role {
+            Role::System => "system",
+            Role::User | Role::Function => "user",
+            Role::Model => "assistant",
+        };
+        let mut formatted_message = json!({ "role": role, "content": message.text() });
+        if !tool_calls.is_empty() {
+            formatted_message["tool_calls"] = Value::Array(tool_calls);
+        }
+        formatted.push(formatted_message);
+    }
+    
+    formatted
+}
+
+fn into_model_response(response: ChatResponse) -> Result<ModelResponse> {
+    if let Some(error) = response.error {
+        return Err(eyre!("Ollama error: {}", error));
+    }
+    
+    let Some(message) = response.message else {
+        return Ok(ModelResponse::default());
+    };
+    
+    Ok(ModelResponse {
+        text: message.content,
+        function_calls: message.tool_calls.into_iter()
+            .map(|call| FunctionCall {
+                id: None,
+                name: call.function.name,
+                args: call.function.arguments,
+            })
+            .collect(),
+    })
+}
+
+struct StreamState {
+    response: reqwest::Response,
+    buffer: Vec<u8>,
+    done: bool,
+}
+
+/// Ollama streams newline-delimited JSON objects rather than SSE events
+async fn next_stream_chunk(mut state: StreamState) -> Option<(Result<ModelResponse>, StreamState)> {
+    loop {
+        if state.done {
+            return None;
+        }
+        
+        let line = match state.buffer.iter().position(|&b| b == b'\n') {
+            Some(end) => state.buffer.drain(..=end).collect::<Vec<_>>(),
+            None => match state.response.chunk().await {
+                Ok(Some(bytes)) => {
+                    state.buffer.extend_from_slice(&bytes);
+                    continue;
+                }
+                Ok(None) => {
+                    state.done = true;
+                    std::mem::take(&mut state.buffer)
+                }
+                Err(e) => {
+                    state.done = true;
+                    return Some((Err(e.into()), state));
+                }
+            },
+        };
+        
+        let line = String::from_utf8_lossy(&line);
+        if line.trim().is_empty() {
+            continue;
+        }
+        
+        debug!("Received stream chunk from Ollama: {}", line);
+        
+        let chunk: ChatResponse = match serde_json::from_str(&line) {
+            Ok(chunk) => chunk,
+            Err(e) => {
+                state.done = true;
+                return Some((Err(e.into()), state));
+            }
+        };
+        
+        if chunk.done {
+            state.done = true;
+        }
+        
+        match into_model_response(chunk) {
+            Ok(result) if result.text.is_empty() && result.function_calls.is_empty() => continue,
+            Ok(result) => return Some((Ok(result), state)),
+            Err(e) => {
+                state.done = true;
+                return Some((Err(e), state));
+            }
+        }
+    }
+}
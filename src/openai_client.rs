@@ -0,0 +1,453 @@
+use std::env;
+
+use async_trait::async_trait;
+use eyre::{Result, eyre};
+use futures::stream::{self, BoxStream, StreamExt};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tracing::{debug, error};
+
+use crate::model_provider::{
+    Content,
+    FunctionCall,
+    GenerationConfig,
+    ModelError,
+    ModelProvider,
+    ModelResponse,
+    Part,
+    Role,
+    ToolDefinition,
+};
+use crate::redact;
+use crate::sse::SseDecoder;
+
+const DEFAULT_BASE_URL: &str = "https://api.openai.com/v1";
+const DEFAULT_MODEL: &str = "gpt-4o-mini";
+
+/// Input token limit assumed for OpenAI-compatible models
+const CONTEXT_WINDOW: usize = 128_000;
+
+#[derive(Debug, Deserialize)]
+struct ChatCompletionResponse {
+    #[serde(default)]
+    choices: Vec<Choice>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Choice {
+    message: Option<ChoiceMessage>,
+    delta: Option<ChoiceMessage>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct ChoiceMessage {
+    content: Option<String>,
+    #[serde(default)]
+    tool_calls: Vec<ToolCall>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ToolCall {
+    #[serde(default)]
+    index: usize,
+    id: Option<String>,
+    function: Option<ToolCallFunction>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ToolCallFunction {
+    name: Option<String>,
+    arguments: Option<String>,
+}
+
+/// Client for any server implementing the OpenAI chat completions API.
+///
+/// The base URL is taken from the global configuration or `OPENAI_BASE_URL`,
+/// which is not read from `.env` files. This makes it possible to point the
+/// client at a local mock server or a self-hosted model. An API key from
+/// `OPENAI_API_KEY` is only required for the default OpenAI endpoint.
+pub struct OpenAiClient {
+    api_key: Option<String>,
+    base_url: String,
+    model: String,
+    generation: GenerationConfig,
+    client: reqwest::Client,
+}
+
+impl OpenAiClient {
+    pub fn new(model: Option<String>, base_url: Option<String>, generation: GenerationConfig) -> Result<Self> {
+        let api_key = env::var("OPENAI_API_KEY").ok();
+        if let Some(api_key) = &api_key {
+            redact::register_secret(api_key);
+        }
+        let base_url = base_url
+            .or_else(|| env::var("OPENAI_BASE_URL").ok())
+            .unwrap_or_else(|| DEFAULT_BASE_URL.to_string());
+        
+        if api_key.is_none() && base_url == DEFAULT_BASE_URL {
+            return Err(eyre!("OPENAI_API_KEY environment variable not set"));
+        }
+        
+        Ok(Self {
+            api_key,
+            base_url: base_url.trim_end_matches('/').to_string(),
+            model: model
+                .or_else(|| env::var("OPENAI_MODEL").ok())
+                .unwrap_or_else(|| DEFAULT_MODEL.to_string()),
+            generation,
+            client: reqwest::Client::new(),
+        })
+    }
+    
+    async fn send_request(
+        &self,
+        system_prompt: &str,
+        messages: &[Content],
+        tools: &[ToolDefinition],
+        stream: bool,
+    ) -> Result<reqwest::Response> {
+        let api_url = format!("{}/chat/completions", self.base_url);
+        
+        let mut request_body = json!({
+            "model": self.model,
+            "messages": format_messages(system_prompt, messages),
+            "temperature": self.generation.temperature.unwrap_or(0.2),
+            "stream": stream,
+        });
+        
+        // Only send sampling parameters the user set, as support varies between servers
+        if let Some(top_p) = self.generation.top_p {
+            request_body["top_p"] = json!(top_p);
+        }
+        if let Some(max_output_tokens) = self.generation.max_output_tokens {
+            request_body["max_tokens"] = json!(max_output_tokens);
+        }
+        
+        if !tools.is_empty() {
+            request_body["tools"] = tools.iter().map(|tool| {
+                json!({
+                    "type": "function",
+                    "function": {
+                        "name": tool.name,
+                        "description": tool.description,
+                        "parameters": tool.parameters
+                    }
+                })
+            }).collect();
+        }
+        
+        debug!("Sending request to OpenAI-compatible API: {}", serde_json::to_string_pretty(&request_body)?);
+        
+        let mut request = self.client.post(&api_url).json(&request_body);
+        if let Some(api_key) = &self.api_key {
+            request = request.bearer_auth(api_key);
+        }
+        
+        let response = request.send().await?;
+        
+        let status = response.status();
+        if !status.is_success() {
+            let error_text = response.text().await?;
+            error!("API request failed with response: {}", error_text);
+            return Err(ModelError::from_response_body(status.as_u16(), &error_text).into());
+        }
+        
+        Ok(response)
+    }
+}
+
+#[async_trait]
+impl ModelProvider for OpenAiClient {
+    fn name(&self) -> String {
+        format!("OpenAI-compatible ({})", self.model)
+    }
+    
+    fn supports_tools(&self) -> bool {
+        true
+    }
+    
+    fn context_window(&self) -> usize {
+        CONTEXT_WINDOW
+    }
+    
+    async fn generate(
+        &self,
+        system_prompt: &str,
+        messages: &[Content],
+        tools: &[ToolDefinition],
+    ) -> Result<ModelResponse> {
+        let response = self.send_request(system_prompt, messages, tools, false).await?;
+        let response_json: Value = response.json().await?;
+        
+        debug!("Received response from OpenAI-compatible API: {}", serde_json::to_string_pretty(&response_json)?);
+        
+        let response: ChatCompletionResponse = serde_json::from_value(response_json)?;
+        let message = response.choices.into_iter()
+            .next()
+            .and_then(|choice| choice.message)
+            .ok_or(ModelError::EmptyResponse { finish_reason: None })?;
+        
+        let function_calls = message.tool_calls.into_iter()
+            .map(|call| {
+                let function = call.function.unwrap_or(ToolCallFunction { name: None, arguments: None });
+                FunctionCall {
+                    id: call.id,
+                    name: function.name.unwrap_or_default(),
+                    args: parse_arguments(function.arguments.unwrap_or_default()),
+                }
+            })
+            .collect();
+        
+        Ok(ModelResponse {
+            text: message.content.unwrap_or_default(),
+            function_calls,
+        })
+    }
+    
+    async fn stream(
+        &self,
+        system_prompt: &str,
+        messages: &[Content],
+        tools: &[ToolDefinition],
+    ) -> Result<BoxStream<'static, Result<ModelResponse>>> {
+        let response = self.send_request(system_prompt, messages, tools, true).await?;
+        
+        let state = StreamState {
+            response,
+            decoder: SseDecoder::new(),
+            pending_calls: Vec::new(),
+            done: false,
+        };
+        
+        Ok(stream::unfold(state, next_stream_chunk).boxed())
+    }
+}
+
+/// Convert the conversation into chat completion messages. Function calls
+/// become `tool_calls` on assistant messages and function responses become
+/// `tool` messages referencing the call id.
+fn format_messages(system_prompt: &str, messages: &[Content]) -> Vec<Value> {
+    let mut formatted = vec![json!({ "role": "system", "content": system_prompt })];
+    
+    for message in messages {
+        let mut tool_calls = Vec::new();
+        let mut tool_results = Vec::new();
+        
+        for part in &message.parts {
+            match part {
+                Part::Text(_) => {},
+                Part::FunctionCall(call) => tool_calls.push(json!({
+                    "id": call.id.clone().unwrap_or_else(|| format!("call_{}", tool_calls.len())),
+                    "type": "function",
+                    "function": {
+                        "name": call.name,
+                        "arguments": call.args.to_string()
+                    }
+                })),
+                Part::FunctionResponse(response) => tool_results.push(json!({
+                    "role": "tool",
+                    "tool_call_id": response.id.clone().unwrap_or_else(|| format!("call_{}", tool_results.len())),
+                    "content": response.response.to_string()
+                })),
+            }
+        }
+        
+        if !tool_results.is_empty() {
+            formatted.extend(tool_results);
+            continue;
+        }
+        
+        let role = match message.role {
+            Role::System => "system",
+            Role::User | Role::Function => "user",
+            Role::Model => "assistant",
+        };
+        let mut formatted_message = json!({ "role": role, "content": message.text() });
+        if !tool_calls.is_empty() {
+            formatted_message["tool_calls"] = Value::Array(tool_calls);
+        }
+        formatted.push(formatted_message);
+    }
+    
+    formatted
+}
+
+/// Function arguments arrive as a JSON-encoded string. If the model produced
+/// invalid JSON the raw string is kept so the tool can report the problem.
+fn parse_arguments(arguments: String) -> Value {
+    if arguments.trim().is_empty() {
+        return json!({});
+    }
+    serde_json::from_str(&arguments).unwrap_or(Value::String(arguments))
+}
+
+/// A tool call whose name and arguments are still being streamed
+#[derive(Default)]
+struct PendingCall {
+    id: Option<String>,
+    name: String,
+    arguments: String,
+}
+
+struct StreamState {
+    response: reqwest::Response,
+    decoder: SseDecoder,
+    pending_calls: Vec<PendingCall>,
+    done: bool,
+}
+
+async fn next_stream_chunk(mut state: StreamState) -> Option<(Result<ModelResponse>, StreamState)> {
+    loop {
+        if state.done {
+            return None;
+        }
+        
+        let event = match state.decoder.next_event() {
+            Some(event) => Some(event),
+            None => match state.response.chunk().await {
+                Ok(Some(bytes)) => {
+                    state.decoder.push(&bytes);
+                    continue;
+                }
+                Ok(None) => state.decoder.finish(),
+                Err(e) => {
+                    state.done = true;
+                    return Some((Err(e.into()), state));
+                }
+            },
+        };
+        
+        // Tool call arguments are only complete once the stream ends
+        let Some(event) = event.filter(|event| event.trim() != "[DONE]") else {
+            state.done = true;
+            let function_calls = state.pending_calls.drain(..)
+                .map(|call| FunctionCall {
+                    id: call.id,
+                    name: call.name,
+                    args: parse_arguments(call.arguments),
+                })
+                .collect::<Vec<_>>();
+            if function_calls.is_empty() {
+                return None;
+            }
+            return Some((Ok(ModelResponse { text: String::new(), function_calls }), state));
+        };
+        
+        debug!("Received stream chunk from OpenAI-compatible API: {}", event);
+        
+        let chunk: ChatCompletionResponse = match serde_json::from_str(&event) {
+            Ok(chunk) => chunk,
+            Err(e) => {
+                state.done = true;
+                return Some((Err(e.into()), state));
+            }
+        };
+        
+        let Some(delta) = chunk.choices.into_iter().next().and_then(|choice| choice.delta) else {
+            continue;
+        };
+        
+        for call in delta.tool_calls {
+            if state.pending_calls.len() <= call.index {
+                state.pending_calls.resize_with(call.index + 1, PendingCall::default);
+            }
+            let pending = &mut state.pending_calls[call.index];
+            if call.id.is_some() {
+                pending.id = call.id;
+            }
+            if let Some(function) = call.function {
+                if let Some(name) = function.name {
+                    pending.name.push_str(&name);
+                }
+                if let Some(arguments) = function.arguments {
+                    pending.arguments.push_str(&arguments);
+                }
+            }
+        }
+        
+        match delta.content {
+            Some(text) if !text.is_empty() => {
+                return Some((Ok(ModelResponse { text, function_calls: Vec::new() }), state));
+            }
+            _ => continue,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_server::{self, MockServer};
+    use crate::model_provider::FunctionResponse;
+
+    fn client(server: &MockServer) -> OpenAiClient {
+        OpenAiClient {
+            api_key: None,
+            base_url: server.url.clone(),
+            model: DEFAULT_MODEL.to_string(),
+            generation: GenerationConfig::default(),
+            client: reqwest::Client::new(),
+        }
+    }
+
+    fn event_stream(events: &[Value]) -> String {
+        let mut body: String = events.iter().map(|event| format!("data: {}\n\n", event)).collect();
+        body.push_str("data: [DONE]\n\n");
+        mock_server::response(200, &[("Content-Type", "text/event-stream")], &body)
+    }
+
+    fn tool_call_delta(id: Option<&str>, name: Option<&str>, arguments: &str) -> Value {
+        json!({ "choices": [{ "delta": { "tool_calls": [{
+            "index": 0,
+            "id": id,
+            "function": { "name": name, "arguments": arguments }
+        }] } }] })
+    }
+
+    #[tokio::test]
+    async fn streamed_tool_call_round_trip() {
+        let server = MockServer::start(vec![
+            event_stream(&[
+                json!({ "choices": [{ "delta": { "content": "Reading it" } }] }),
+                tool_call_delta(Some("call_abc"), Some("fs_read"), ""),
+                tool_call_delta(None, None, r#"{"path": "a."#),
+                tool_call_delta(None, None, r#"txt", "mode": "Line"}"#),
+            ]),
+            event_stream(&[json!({ "choices": [{ "delta": { "content": "Done" } }] })]),
+        ])
+        .await;
+        let client = client(&server);
+        let mut messages = vec![Content::text_message(Role::User, "Read a.txt")];
+
+        let responses: Vec<ModelResponse> = client.stream("", &messages, &[]).await.unwrap()
+            .map(Result::unwrap)
+            .collect()
+            .await;
+
+        assert_eq!(responses.len(), 2);
+        assert_eq!(responses[0].text, "Reading it");
+        let call = &responses[1].function_calls[0];
+        assert_eq!(call.id.as_deref(), Some("call_abc"));
+        assert_eq!(call.name, "fs_read");
+        assert_eq!(call.args, json!({ "path": "a.txt", "mode": "Line" }));
+
+        messages.push(Content::new(Role::Model, vec![Part::FunctionCall(call.clone())]));
+        messages.push(Content::new(Role::Function, vec![Part::FunctionResponse(FunctionResponse {
+            id: call.id.clone(),
+            name: call.name.clone(),
+            response: json!({ "output": "hello" }),
+        })]));
+        let responses: Vec<ModelResponse> = client.stream("", &messages, &[]).await.unwrap()
+            .map(Result::unwrap)
+            .collect()
+            .await;
+        assert_eq!(responses[0].text, "Done");
+
+        let request: Value = serde_json::from_str(&server.requests()[1]).unwrap();
+        let sent = &request["messages"];
+        assert_eq!(sent[2]["tool_calls"][0]["id"], "call_abc");
+        assert_eq!(sent[2]["tool_calls"][0]["function"]["arguments"], call.args.to_string());
+        assert_eq!(sent[3]["role"], "tool");
+        assert_eq!(sent[3]["tool_call_id"], "call_abc");
+    }
+}
@@ -0,0 +1,130 @@
+use std::borrow::Cow;
+use std::env;
+use std::io::{self, Write};
+use std::sync::RwLock;
+
+use tracing_subscriber::fmt::MakeWriter;
+
+/// Text written in place of a secret
+const REDACTED: &str = "[REDACTED]";
+
+/// Values shorter than this are not treated as secrets, as replacing them
+/// would mangle unrelated text
+const MIN_SECRET_LEN: usize = 8;
+
+/// Suffixes of environment variables whose values are treated as secrets
+const SECRET_ENV_SUFFIXES: &[&str] = &["_API_KEY", "_TOKEN", "_SECRET", "_SECRET_ACCESS_KEY", "_PASSWORD"];
+
+static SECRETS: RwLock<Vec<String>> = RwLock::new(Vec::new());
+
+/// Register a value that must never appear in log output
+pub fn register_secret(secret: &str) {
+    let secret = secret.trim();
+    if secret.len() < MIN_SECRET_LEN {
+        return;
+    }
+
+    let mut secrets = SECRETS.write().unwrap_or_else(|e| e.into_inner());
+    if !secrets.iter().any(|s| s == secret) {
+        secrets.push(secret.to_string());
+    }
+}
+
+/// Register the values of all environment variables that look like
+/// credentials, e.g. `GEMINI_API_KEY` or `AWS_SESSION_TOKEN`
+pub fn register_env_secrets() {
+    for (name, value) in env::vars() {
+        let name = name.to_uppercase();
+        if SECRET_ENV_SUFFIXES.iter().any(|suffix| name.ends_with(suffix)) {
+            register_secret(&value);
+        }
+    }
+}
+
+/// Replace every registered secret in `text`
+pub fn redact(text: &str) -> Cow<'_, str> {
+    let secrets = SECRETS.read().unwrap_or_else(|e| e.into_inner());
+    if !secrets.iter().any(|secret| text.contains(secret.as_str())) {
+        return Cow::Borrowed(text);
+    }
+
+    let mut redacted = text.to_string();
+    for secret in secrets.iter() {
+        redacted = redacted.replace(secret.as_str(), REDACTED);
+    }
+    Cow::Owned(redacted)
+}
+
+/// A `MakeWriter` for the tracing subscriber that removes registered secrets
+/// from every log line before it reaches the underlying writer
+pub struct RedactingMakeWriter<M> {
+    inner: M,
+}
+
+impl<M> RedactingMakeWriter<M> {
+    pub fn new(inner: M) -> Self {
+        Self { inner }
+    }
+}
+
+impl<'a, M: MakeWriter<'a>> MakeWriter<'a> for RedactingMakeWriter<M> {
+    type Writer = RedactingWriter<M::Writer>;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        RedactingWriter { inner: self.inner.make_writer() }
+    }
+}
+
+/// Writer returned by `RedactingMakeWriter`. The formatter writes each event
+/// in a single call, so secrets are never split across writes.
+pub struct RedactingWriter<W> {
+    inner: W,
+}
+
+impl<W: Write> Write for RedactingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let text = String::from_utf8_lossy(buf);
+        self.inner.write_all(redact(&text).as_bytes())?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The registry is global, so every test uses secrets of its own
+
+    #[test]
+    fn replaces_registered_secrets() {
+        register_secret("  AIzaSyTest-first-secret  ");
+        register_secret("second-secret-value");
+
+        assert_eq!(
+            redact("key=AIzaSyTest-first-secret&other=second-secret-value"),
+            "key=[REDACTED]&other=[REDACTED]"
+        );
+        assert!(matches!(redact("nothing to hide"), Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn ignores_short_values() {
+        register_secret("1234");
+        assert_eq!(redact("port 1234"), "port 1234");
+    }
+
+    #[test]
+    fn redacts_what_is_written_to_the_log() {
+        register_secret("log-writer-secret");
+
+        let mut output = Vec::new();
+        let mut writer = RedactingWriter { inner: &mut output };
+        writer.write_all(b"sending x-goog-api-key: log-writer-secret\n").unwrap();
+
+        assert_eq!(String::from_utf8(output).unwrap(), "sending x-goog-api-key: [REDACTED]\n");
+    }
+}
@@ -0,0 +1,196 @@
+//! Retry policy for transient API failures.
+//!
+//! Rate limits (429) and temporary server errors are retried with jittered
+//! exponential backoff. When the server says how long to wait, either with a
+//! `Retry-After` header or a `google.rpc.RetryInfo` detail in the error body,
+//! that delay is used instead.
+
+use std::collections::hash_map::RandomState;
+use std::fmt;
+use std::hash::{BuildHasher, Hasher};
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use reqwest::header::{HeaderMap, RETRY_AFTER};
+use reqwest::StatusCode;
+use serde_json::Value;
+
+/// Attempts made for a request, including the first one
+pub const DEFAULT_MAX_ATTEMPTS: u32 = 4;
+
+/// Time allowed for a complete request, including a streamed response body
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// Backoff delay before the first retry, doubled for every further attempt
+const BASE_DELAY: Duration = Duration::from_secs(1);
+
+/// Longest delay the client is willing to wait before retrying. Longer
+/// delays requested by the server (e.g. an exhausted daily quota) fail the
+/// request instead.
+pub const MAX_DELAY: Duration = Duration::from_secs(60);
+
+/// How often and how long a client retries failed requests
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    pub max_attempts: u32,
+    pub timeout: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: DEFAULT_MAX_ATTEMPTS,
+            timeout: DEFAULT_TIMEOUT,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Backoff delay after the given failed attempt (starting at 1).
+    ///
+    /// The delay doubles with every attempt and a random jitter of up to
+    /// half the delay is subtracted, so that clients failing at the same
+    /// time don't retry in lockstep.
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let exponent = attempt.saturating_sub(1).min(16);
+        let delay = BASE_DELAY.saturating_mul(1 << exponent).min(MAX_DELAY);
+        let jitter = delay.mul_f64(random_fraction() / 2.0);
+        delay - jitter
+    }
+}
+
+/// Whether a response with this status may succeed when retried
+pub fn is_retryable(status: StatusCode) -> bool {
+    matches!(
+        status,
+        StatusCode::TOO_MANY_REQUESTS
+            | StatusCode::INTERNAL_SERVER_ERROR
+            | StatusCode::BAD_GATEWAY
+            | StatusCode::SERVICE_UNAVAILABLE
+            | StatusCode::GATEWAY_TIMEOUT
+    )
+}
+
+/// Delay requested by a `Retry-After` header, given either in seconds or as
+/// an HTTP date
+pub fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let value = headers.get(RETRY_AFTER)?.to_str().ok()?.trim();
+
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+
+    let date = DateTime::parse_from_rfc2822(value).ok()?;
+    (date.with_timezone(&Utc) - Utc::now()).to_std().ok()
+}
+
+/// Delay requested by a `google.rpc.RetryInfo` entry in the `details` of a
+/// Gemini error body, e.g. `{"retryDelay": "13s"}`
+pub fn retry_info_delay(body: &str) -> Option<Duration> {
+    let json: Value = serde_json::from_str(body).ok()?;
+    let details = json.get("error")?.get("details")?.as_array()?;
+
+    details
+        .iter()
+        .filter(|detail| {
+            detail
+                .get("@type")
+                .and_then(|t| t.as_str())
+                .is_some_and(|t| t.ends_with("google.rpc.RetryInfo"))
+        })
+        .find_map(|detail| {
+            let delay = detail.get("retryDelay")?.as_str()?;
+            let seconds = delay.strip_suffix('s')?.parse::<f64>().ok()?;
+            Duration::try_from_secs_f64(seconds).ok()
+        })
+}
+
+/// Sent by a client before it waits to retry a failed request, so that the
+/// chat can tell the user why nothing is happening
+#[derive(Debug, Clone)]
+pub struct RetryNotice {
+    /// Why the previous attempt failed
+    pub reason: String,
+
+    /// How long the client waits before the next attempt
+    pub delay: Duration,
+
+    /// The attempt about to be made, starting at 2
+    pub attempt: u32,
+
+    pub max_attempts: u32,
+}
+
+impl fmt::Display for RetryNotice {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}. Retrying in {}s (attempt {}/{})...",
+            self.reason.trim_end_matches('.'),
+            self.delay.as_secs_f64().ceil() as u64,
+            self.attempt,
+            self.max_attempts
+        )
+    }
+}
+
+/// A random number in `[0, 1)`, seeded from the hasher keys the standard
+/// library generates for every `RandomState`
+fn random_fraction() -> f64 {
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u64(0);
+    (hasher.finish() >> 11) as f64 / (1u64 << 53) as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::header::HeaderValue;
+
+    use super::*;
+
+    fn headers(retry_after: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(RETRY_AFTER, HeaderValue::from_str(retry_after).unwrap());
+        headers
+    }
+
+    #[test]
+    fn reads_retry_after_in_seconds() {
+        assert_eq!(retry_after(&headers("7")), Some(Duration::from_secs(7)));
+        assert_eq!(retry_after(&HeaderMap::new()), None);
+        assert_eq!(retry_after(&headers("soon")), None);
+    }
+
+    #[test]
+    fn reads_retry_after_as_a_date() {
+        let date = (Utc::now() + chrono::Duration::seconds(30)).to_rfc2822();
+        let delay = retry_after(&headers(&date)).unwrap();
+        assert!(delay > Duration::from_secs(25) && delay <= Duration::from_secs(30));
+
+        // A date in the past asks for no particular delay
+        let date = (Utc::now() - chrono::Duration::seconds(30)).to_rfc2822();
+        assert_eq!(retry_after(&headers(&date)), None);
+    }
+
+    #[test]
+    fn reads_retry_info_from_gemini_errors() {
+        let body = r#"{"error": {"code": 429, "details": [
+            {"@type": "type.googleapis.com/google.rpc.QuotaFailure"},
+            {"@type": "type.googleapis.com/google.rpc.RetryInfo", "retryDelay": "13.5s"}
+        ]}}"#;
+        assert_eq!(retry_info_delay(body), Some(Duration::from_millis(13500)));
+
+        assert_eq!(retry_info_delay(r#"{"error": {"code": 429}}"#), None);
+        assert_eq!(retry_info_delay("Too Many Requests"), None);
+    }
+
+    #[test]
+    fn backs_off_exponentially_with_jitter() {
+        let policy = RetryPolicy::default();
+        for attempt in 1..=10 {
+            let full = BASE_DELAY.saturating_mul(1 << (attempt - 1)).min(MAX_DELAY);
+            let delay = policy.backoff(attempt);
+            assert!(delay <= full && delay >= full / 2, "attempt {}: {:?}", attempt, delay);
+        }
+    }
+}
@@ -0,0 +1,98 @@
+/// Incremental decoder for `text/event-stream` bodies.
+///
+/// Bytes are pushed in as they arrive from the network and complete events
+/// are returned as the concatenated payload of their `data:` lines. Events
+/// may be split across any number of chunks, including in the middle of a
+/// multi-byte UTF-8 sequence.
+#[derive(Debug, Default)]
+pub struct SseDecoder {
+    buffer: Vec<u8>,
+}
+
+impl SseDecoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Append raw bytes received from the response body
+    pub fn push(&mut self, bytes: &[u8]) {
+        self.buffer.extend(bytes.iter().filter(|&&b| b != b'\r'));
+    }
+
+    /// Return the data payload of the next complete event, if any
+    pub fn next_event(&mut self) -> Option<String> {
+        loop {
+            let end = self.buffer.windows(2).position(|w| w == b"\n\n")?;
+            let raw: Vec<u8> = self.buffer.drain(..end + 2).collect();
+            if let Some(data) = parse_event(&raw) {
+                return Some(data);
+            }
+        }
+    }
+
+    /// Return the data payload of a trailing event that was not terminated
+    /// by a blank line before the stream ended
+    pub fn finish(&mut self) -> Option<String> {
+        let raw = std::mem::take(&mut self.buffer);
+        parse_event(&raw)
+    }
+}
+
+fn parse_event(raw: &[u8]) -> Option<String> {
+    let text = String::from_utf8_lossy(raw);
+    let data = text
+        .lines()
+        .filter_map(|line| line.strip_prefix("data:"))
+        .map(|line| line.strip_prefix(' ').unwrap_or(line))
+        .collect::<Vec<_>>();
+
+    if data.is_empty() {
+        None
+    } else {
+        Some(data.join("\n"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_events_split_across_chunks() {
+        let mut decoder = SseDecoder::new();
+        decoder.push(b"data: {\"a\":");
+        assert_eq!(decoder.next_event(), None);
+
+        decoder.push(b"1}\r\n\r\ndata: second\n\n");
+        assert_eq!(decoder.next_event().as_deref(), Some("{\"a\":1}"));
+        assert_eq!(decoder.next_event().as_deref(), Some("second"));
+        assert_eq!(decoder.next_event(), None);
+    }
+
+    #[test]
+    fn keeps_multi_byte_characters_split_across_chunks() {
+        let bytes = "data: héllo\n\n".as_bytes();
+        let split = bytes.iter().position(|&b| b == 0xc3).unwrap() + 1;
+
+        let mut decoder = SseDecoder::new();
+        decoder.push(&bytes[..split]);
+        decoder.push(&bytes[split..]);
+        assert_eq!(decoder.next_event().as_deref(), Some("héllo"));
+    }
+
+    #[test]
+    fn joins_data_lines_and_skips_other_fields() {
+        let mut decoder = SseDecoder::new();
+        decoder.push(b": keep-alive\n\nevent: message\ndata: one\ndata:two\nid: 7\n\n");
+        assert_eq!(decoder.next_event().as_deref(), Some("one\ntwo"));
+    }
+
+    #[test]
+    fn returns_an_unterminated_event_at_the_end() {
+        let mut decoder = SseDecoder::new();
+        decoder.push(b"data: last");
+        assert_eq!(decoder.next_event(), None);
+        assert_eq!(decoder.finish().as_deref(), Some("last"));
+        assert_eq!(decoder.finish(), None);
+    }
+}